@@ -0,0 +1,224 @@
+use anyhow::{bail, ensure, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::collections::BTreeMap;
+
+pub(crate) fn to_cbor<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(value, &mut bytes)?;
+    Ok(bytes)
+}
+
+pub(crate) fn from_cbor<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    Ok(ciborium::de::from_reader(bytes)?)
+}
+
+/// Every part starts with this, the format borrows the shape of Blockchain Commons UR but is
+/// not UR: bodies are hex instead of bytewords and the checksum is not a CRC32.
+const PART_PREFIX: &str = "zklink:";
+
+/// The body of a multipart message: `[seq, count, message_len, checksum, fragment]`.
+#[derive(Serialize, Deserialize)]
+struct Part(u32, u32, u32, u32, #[serde(with = "serde_bytes")] Vec<u8>);
+
+fn message_checksum(message: &[u8]) -> u32 {
+    let hash = Keccak256::digest(message);
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+/// Split `message` into `zklink:<type>/<seq>-<count>/<body>` parts to show as an animated QR
+/// code, a message that fits in one fragment becomes a single `zklink:<type>/<body>` part.
+/// Everything is uppercase so the QR code can use alphanumeric mode. There is no fountain
+/// coding, the parts are meant to be shown in a loop until all are scanned.
+pub(crate) fn encode_parts(
+    part_type: &str,
+    message: &[u8],
+    max_fragment_len: usize,
+) -> Result<Vec<String>> {
+    ensure!(max_fragment_len > 0, "fragment length must be positive");
+    if message.len() <= max_fragment_len {
+        return Ok(vec![format!(
+            "{PART_PREFIX}{part_type}/{}",
+            hex::encode(message)
+        )
+        .to_uppercase()]);
+    }
+    let count = message.len().div_ceil(max_fragment_len) as u32;
+    let checksum = message_checksum(message);
+    message
+        .chunks(max_fragment_len)
+        .zip(1..)
+        .map(|(data, seq)| {
+            let body = to_cbor(&Part(
+                seq,
+                count,
+                message.len() as u32,
+                checksum,
+                data.to_vec(),
+            ))?;
+            Ok(format!(
+                "{PART_PREFIX}{part_type}/{seq}-{count}/{}",
+                hex::encode(body)
+            )
+            .to_uppercase())
+        })
+        .collect()
+}
+
+/// Collects scanned parts in any order, duplicates included, until the message is complete.
+/// A part that fails to decode leaves the decoder as it was.
+#[derive(Default)]
+pub(crate) struct PartDecoder {
+    part_type: Option<String>,
+    /// `(count, message_len, checksum)` of the message being collected.
+    expected: Option<(u32, u32, u32)>,
+    fragments: BTreeMap<u32, Vec<u8>>,
+    message: Option<Vec<u8>>,
+}
+
+impl PartDecoder {
+    fn check_type(&self, part_type: &str) -> Result<()> {
+        if let Some(expected) = &self.part_type {
+            ensure!(
+                expected == part_type,
+                "expected a {expected} part, got {part_type}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Feed one scanned part, returns true once the message is complete.
+    pub fn receive(&mut self, part: &str) -> Result<bool> {
+        if self.message.is_some() {
+            return Ok(true);
+        }
+        let part = part.trim().to_lowercase();
+        let Some(rest) = part.strip_prefix(PART_PREFIX) else {
+            bail!("not a zkLink QR part");
+        };
+        match rest.split('/').collect::<Vec<_>>().as_slice() {
+            [part_type, body] => {
+                self.check_type(part_type)?;
+                ensure!(
+                    self.fragments.is_empty(),
+                    "single part received while collecting a multipart message"
+                );
+                let message = hex::decode(body)?;
+                self.part_type = Some(part_type.to_string());
+                self.message = Some(message);
+            }
+            [part_type, _, body] => {
+                self.check_type(part_type)?;
+                let Part(seq, count, message_len, checksum, data) = from_cbor(&hex::decode(body)?)?;
+                let expected = (count, message_len, checksum);
+                ensure!(
+                    self.expected.is_none_or(|e| e == expected),
+                    "part belongs to another message"
+                );
+                ensure!((1..=count).contains(&seq), "part {seq} out of 1-{count}");
+                let mut fragments = self.fragments.clone();
+                fragments.insert(seq, data);
+                let mut message = None;
+                if fragments.len() == count as usize {
+                    let full: Vec<u8> = fragments.values().flatten().copied().collect();
+                    ensure!(
+                        full.len() == message_len as usize && message_checksum(&full) == checksum,
+                        "message checksum mismatch"
+                    );
+                    message = Some(full);
+                }
+                self.part_type = Some(part_type.to_string());
+                self.expected = Some(expected);
+                self.fragments = fragments;
+                self.message = message;
+            }
+            _ => bail!("malformed zkLink QR part"),
+        }
+        Ok(self.message.is_some())
+    }
+
+    pub fn part_type(&self) -> Option<&str> {
+        self.part_type.as_deref()
+    }
+
+    /// The share of parts received so far, between 0 and 1.
+    pub fn progress(&self) -> f64 {
+        match (&self.message, self.expected) {
+            (Some(_), _) => 1.0,
+            (None, Some((count, _, _))) => self.fragments.len() as f64 / count as f64,
+            (None, None) => 0.0,
+        }
+    }
+
+    pub fn message(&self) -> Option<&[u8]> {
+        self.message.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(parts: &[String]) -> PartDecoder {
+        let mut decoder = PartDecoder::default();
+        for part in parts {
+            decoder.receive(part).unwrap();
+        }
+        decoder
+    }
+
+    #[test]
+    fn single_part() {
+        let parts = encode_parts("signature", b"abc", 10).unwrap();
+        assert_eq!(parts, vec!["ZKLINK:SIGNATURE/616263"]);
+        let decoder = decode(&parts);
+        assert_eq!(decoder.part_type(), Some("signature"));
+        assert_eq!(decoder.message(), Some(&b"abc"[..]));
+    }
+
+    #[test]
+    fn multipart_in_any_order_with_duplicates() {
+        let message: Vec<u8> = (0..100).collect();
+        let mut parts = encode_parts("sign-request", &message, 30).unwrap();
+        assert_eq!(parts.len(), 4);
+        assert!(parts[0].starts_with("ZKLINK:SIGN-REQUEST/1-4/"));
+        parts.reverse();
+        parts.insert(1, parts[0].clone());
+        let mut decoder = PartDecoder::default();
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(decoder.receive(part).unwrap(), i == parts.len() - 1);
+        }
+        assert_eq!(decoder.progress(), 1.0);
+        assert_eq!(decoder.message(), Some(&message[..]));
+    }
+
+    #[test]
+    fn invalid_part_leaves_the_decoder_untouched() {
+        let message: Vec<u8> = (0..100).collect();
+        let parts = encode_parts("sign-request", &message, 30).unwrap();
+        let mut decoder = PartDecoder::default();
+        assert!(decoder.receive("zklink:signature/zz").is_err());
+        assert_eq!(decoder.part_type(), None);
+        assert!(decoder.receive("zklink:sign-request/1-4/00").is_err());
+        assert_eq!(decoder.part_type(), None);
+        assert_eq!(decoder.progress(), 0.0);
+        decoder.receive(&parts[0]).unwrap();
+        // a part of another message is rejected without dropping what was collected
+        let other = encode_parts("sign-request", &[1; 70], 30).unwrap();
+        assert!(decoder.receive(&other[1]).is_err());
+        assert!(decoder.receive("zklink:signature/00").is_err());
+        assert_eq!(decoder.progress(), 0.25);
+        for part in &parts[1..] {
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.message(), Some(&message[..]));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let mut decoder = PartDecoder::default();
+        assert!(decoder.receive("ur:bytes/616263").is_err());
+        assert!(decoder.receive("zklink:bytes").is_err());
+    }
+}
@@ -1,16 +1,46 @@
-use anyhow::Result;
-use flutter_rust_bridge::frb;
+use crate::air_gap::{encode_parts, from_cbor, to_cbor, PartDecoder};
+use crate::clock::{now_ts, resolve_ts, set_time_source};
+use crate::frb_generated::StreamSink;
+use crate::funding::{funding_payment, next_funding_ts};
+use crate::global_var::decode_update_global_var;
+use crate::l1_event::decode_deposit;
+use crate::l1_tx::{
+    approve_call, build_tx, decode_signed, deposit_erc20_call, deposit_eth_call, ensure_same_tx,
+    full_exit_call, set_auth_pubkey_hash_call, sign_tx, L1TxParams,
+};
+use crate::liquidation::{self, AccountSnapshot as InnerAccountSnapshot};
+use crate::margin::{self, CollateralSnapshot, ContractRisk, MarketSnapshot, PositionSnapshot};
+use crate::matching::{
+    match_contracts, match_spot, ContractFills, ContractSide, OrderSide, SpotFill,
+};
+use crate::order_book::Book;
+use crate::scale::{check_step, format_decimal, parse_decimal};
+use crate::slots::{Invalidated, Slots};
+use anyhow::{ensure, Result};
+use ethers::abi::{encode, AbiParser, LenientTokenizer, Token, Tokenizer};
+use ethers::providers::{Http, Middleware, PendingTransaction, Provider};
+use ethers::signers::{LocalWallet, Signer as _};
+use ethers::types::{Address, BlockNumber, Bytes, TransactionReceipt, TransactionRequest, U256};
+use flutter_rust_bridge::{frb, DartFnFuture};
+use num::{BigInt, Zero};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use serde_json::to_string;
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::time::UNIX_EPOCH;
+use std::sync::Arc;
+use std::time::Duration;
+use validator::Validate;
 use zklink_sdk_interface::signer::{L1SignerType, Signer as InnerSigner};
-use zklink_sdk_signers::eth_signer::{PackedEthSignature, H256};
+use zklink_sdk_signers::eth_signer::{EIP1271Signature, PackedEthSignature, H256};
 use zklink_sdk_signers::starknet_signer::StarkEcdsaSignature;
 use zklink_sdk_signers::zklink_signer::{
     signature::ZkLinkSignature as InnerZkLinkSignature, PubKeyHash,
     ZkLinkSigner as InnerZkLinkSigner,
 };
 use zklink_sdk_types::basic_types::{BigUint, GetBytes, ZkLinkAddress};
+use zklink_sdk_types::signatures::{TxLayer1Signature, TxSignature};
 use zklink_sdk_types::tx_builder::*;
 use zklink_sdk_types::tx_type::change_pubkey::{
     ChangePubKey as InnerChangePubKey, ChangePubKeyAuthData, Create2Data,
@@ -28,17 +58,25 @@ use zklink_sdk_types::tx_type::order_matching::{
 };
 use zklink_sdk_types::tx_type::transfer::Transfer as InnerTransfer;
 use zklink_sdk_types::tx_type::withdraw::Withdraw as InnerWithdraw;
+use zklink_sdk_types::tx_type::zklink_tx::ZkLinkTx as InnerZkLinkTx;
 use zklink_sdk_wallet::eth::EthTxOption as InnerEthTxOption;
 use zklink_sdk_wallet::wallet::Wallet as InnerWallet;
 
 macro_rules! tx_default {
-    () => {
+    (sign) => {
         #[frb(sync)]
         pub fn sign(&mut self, zk_link_signer: ZkLinkSigner) -> Result<()> {
             self.inner.signature = zk_link_signer.inner.sign_musig(&self.inner.get_bytes())?;
             Ok(())
         }
 
+        pub fn sign_async(&mut self, zk_link_signer: ZkLinkSigner) -> Result<()> {
+            self.sign(zk_link_signer)
+        }
+    };
+    () => {
+        tx_default!(sign);
+
         #[frb(sync)]
         pub fn to_json(&self) -> Result<String> {
             Ok(to_string(&self.inner)?)
@@ -105,6 +143,21 @@ impl ZkLinkSigner {
     }
 }
 
+// Non-sync counterparts, executed on the frb thread pool instead of the UI isolate.
+impl ZkLinkSigner {
+    pub fn eth_sig_async(sig: String) -> Result<Self> {
+        Self::eth_sig(sig)
+    }
+
+    pub fn starknet_sig_async(sig: String) -> Result<Self> {
+        Self::starknet_sig(sig)
+    }
+
+    pub fn sign_musig_async(&self, msg: Vec<u8>) -> Result<ZkLinkSignature> {
+        self.sign_musig(msg)
+    }
+}
+
 #[frb(opaque)]
 pub struct Signer {
     pub inner: InnerSigner,
@@ -134,19 +187,48 @@ impl Signer {
     }
 
     #[frb(sync)]
-    pub fn sign_change_pubkey_with_onchain(&self, tx: ChangePubKey) -> Result<String> {
+    pub fn sign_change_pubkey_with_onchain(&self, tx: ChangePubKey) -> Result<SignedTx> {
         let sig = self
             .inner
             .sign_change_pubkey_with_onchain_auth_data(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
-    pub fn sign_change_pubkey_with_eth_ecdsa_auth(&self, tx: ChangePubKey) -> Result<String> {
+    pub fn sign_change_pubkey_with_eth_ecdsa_auth(&self, tx: ChangePubKey) -> Result<SignedTx> {
         let sig = self
             .inner
             .sign_change_pubkey_with_eth_ecdsa_auth(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
+    }
+
+    #[frb(sync)]
+    pub fn sign_change_pubkey_with_starknet_ecdsa_auth(
+        &self,
+        tx: ChangePubKey,
+    ) -> Result<SignedTx> {
+        let sig = self
+            .inner
+            .sign_change_pubkey_with_starknet_ecdsa_auth(tx.inner)?;
+        Ok(SignedTx { inner: sig })
+    }
+
+    /// Sign the zkLink part of the tx and authorize it with an EIP-1271 signature
+    /// produced by the smart contract wallet that owns the account.
+    #[frb(sync)]
+    pub fn sign_change_pubkey_with_eip1271_auth(
+        &self,
+        tx: ChangePubKey,
+        sig: String,
+    ) -> Result<SignedTx> {
+        let signature = EIP1271Signature(hex::decode(sig.trim_start_matches("0x"))?);
+        let mut sig = self
+            .inner
+            .sign_change_pubkey_with_onchain_auth_data(tx.inner)?;
+        if let InnerZkLinkTx::ChangePubKey(tx) = &mut sig.tx {
+            tx.eth_auth_data = ChangePubKeyAuthData::EIP1271 { signature };
+        }
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
@@ -156,7 +238,7 @@ impl Signer {
         creator_address: String,
         salt_arg: String,
         code_hash: String,
-    ) -> Result<String> {
+    ) -> Result<SignedTx> {
         let create2_data = Create2Data {
             creator_address: ZkLinkAddress::from_hex(&creator_address)?,
             code_hash: H256::from_str(&code_hash)?,
@@ -165,7 +247,7 @@ impl Signer {
         let sig = self
             .inner
             .sign_change_pubkey_with_create2data_auth(tx.inner, create2_data)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
@@ -175,11 +257,11 @@ impl Signer {
         token_symbol: String,
         chain_id: Option<String>,
         addr: Option<String>,
-    ) -> Result<String> {
+    ) -> Result<SignedTx> {
         let sig = self
             .inner
             .sign_transfer(tx.inner, &token_symbol, chain_id, addr)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
@@ -189,17 +271,17 @@ impl Signer {
         token_symbol: String,
         chain_id: Option<String>,
         addr: Option<String>,
-    ) -> Result<String> {
+    ) -> Result<SignedTx> {
         let sig = self
             .inner
             .sign_withdraw(tx.inner, &token_symbol, chain_id, addr)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
-    pub fn sign_forced_exit(&self, tx: ForcedExit) -> Result<String> {
+    pub fn sign_forced_exit(&self, tx: ForcedExit) -> Result<SignedTx> {
         let sig = self.inner.sign_forced_exit(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
@@ -210,9 +292,9 @@ impl Signer {
     }
 
     #[frb(sync)]
-    pub fn sign_order_matching(&self, tx: OrderMatching) -> Result<String> {
+    pub fn sign_order_matching(&self, tx: OrderMatching) -> Result<SignedTx> {
         let sig = self.inner.sign_order_matching(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
@@ -223,27 +305,225 @@ impl Signer {
     }
 
     #[frb(sync)]
-    pub fn sign_contract_matching(&self, tx: ContractMatching) -> Result<String> {
+    pub fn sign_contract_matching(&self, tx: ContractMatching) -> Result<SignedTx> {
         let sig = self.inner.sign_contract_matching(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
-    pub fn sign_auto_deleveraging(&self, tx: AutoDeleveraging) -> Result<String> {
+    pub fn sign_auto_deleveraging(&self, tx: AutoDeleveraging) -> Result<SignedTx> {
         let sig = self.inner.sign_auto_deleveraging(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
-    pub fn sign_funding(&self, tx: Funding) -> Result<String> {
+    pub fn sign_funding(&self, tx: Funding) -> Result<SignedTx> {
         let sig = self.inner.sign_funding(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
     }
 
     #[frb(sync)]
-    pub fn sign_liquidation(&self, tx: Liquidation) -> Result<String> {
+    pub fn sign_liquidation(&self, tx: Liquidation) -> Result<SignedTx> {
         let sig = self.inner.sign_liquidation(tx.inner)?;
-        Ok(to_string(&sig.tx)?)
+        Ok(SignedTx { inner: sig })
+    }
+}
+
+// Non-sync counterparts, executed on the frb thread pool instead of the UI isolate.
+impl Signer {
+    pub fn eth_signer_async(eth_private_key: String) -> Result<Self> {
+        Self::eth_signer(eth_private_key)
+    }
+
+    pub fn starknet_signer_async(
+        eth_private_key: String,
+        starknet_chain_id: String,
+        starknet_addr: String,
+    ) -> Result<Self> {
+        Self::starknet_signer(eth_private_key, starknet_chain_id, starknet_addr)
+    }
+
+    pub fn sign_change_pubkey_with_onchain_async(&self, tx: ChangePubKey) -> Result<SignedTx> {
+        self.sign_change_pubkey_with_onchain(tx)
+    }
+
+    pub fn sign_change_pubkey_with_eth_ecdsa_auth_async(
+        &self,
+        tx: ChangePubKey,
+    ) -> Result<SignedTx> {
+        self.sign_change_pubkey_with_eth_ecdsa_auth(tx)
+    }
+
+    pub fn sign_change_pubkey_with_starknet_ecdsa_auth_async(
+        &self,
+        tx: ChangePubKey,
+    ) -> Result<SignedTx> {
+        self.sign_change_pubkey_with_starknet_ecdsa_auth(tx)
+    }
+
+    pub fn sign_change_pubkey_with_eip1271_auth_async(
+        &self,
+        tx: ChangePubKey,
+        sig: String,
+    ) -> Result<SignedTx> {
+        self.sign_change_pubkey_with_eip1271_auth(tx, sig)
+    }
+
+    pub fn sign_change_pubkey_with_create2data_auth_async(
+        &self,
+        tx: ChangePubKey,
+        creator_address: String,
+        salt_arg: String,
+        code_hash: String,
+    ) -> Result<SignedTx> {
+        self.sign_change_pubkey_with_create2data_auth(tx, creator_address, salt_arg, code_hash)
+    }
+
+    pub fn sign_transfer_async(
+        &self,
+        tx: Transfer,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    ) -> Result<SignedTx> {
+        self.sign_transfer(tx, token_symbol, chain_id, addr)
+    }
+
+    pub fn sign_withdraw_async(
+        &self,
+        tx: Withdraw,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    ) -> Result<SignedTx> {
+        self.sign_withdraw(tx, token_symbol, chain_id, addr)
+    }
+
+    pub fn sign_forced_exit_async(&self, tx: ForcedExit) -> Result<SignedTx> {
+        self.sign_forced_exit(tx)
+    }
+
+    pub fn create_signed_order_async(&self, order: Order) -> Result<Order> {
+        self.create_signed_order(order)
+    }
+
+    pub fn sign_order_matching_async(&self, tx: OrderMatching) -> Result<SignedTx> {
+        self.sign_order_matching(tx)
+    }
+
+    pub fn create_signed_contract_async(&self, contract: Contract) -> Result<Contract> {
+        self.create_signed_contract(contract)
+    }
+
+    pub fn sign_contract_matching_async(&self, tx: ContractMatching) -> Result<SignedTx> {
+        self.sign_contract_matching(tx)
+    }
+
+    pub fn sign_auto_deleveraging_async(&self, tx: AutoDeleveraging) -> Result<SignedTx> {
+        self.sign_auto_deleveraging(tx)
+    }
+
+    pub fn sign_funding_async(&self, tx: Funding) -> Result<SignedTx> {
+        self.sign_funding(tx)
+    }
+
+    pub fn sign_liquidation_async(&self, tx: Liquidation) -> Result<SignedTx> {
+        self.sign_liquidation(tx)
+    }
+}
+
+pub struct SignedTxResult {
+    pub tx: Option<SignedTx>,
+    pub error: Option<String>,
+}
+
+impl From<Result<SignedTx>> for SignedTxResult {
+    fn from(res: Result<SignedTx>) -> Self {
+        match res {
+            Ok(tx) => Self {
+                tx: Some(tx),
+                error: None,
+            },
+            Err(e) => Self {
+                tx: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+pub struct SignedOrderResult {
+    pub order: Option<Order>,
+    pub error: Option<String>,
+}
+
+impl From<Result<Order>> for SignedOrderResult {
+    fn from(res: Result<Order>) -> Self {
+        match res {
+            Ok(order) => Self {
+                order: Some(order),
+                error: None,
+            },
+            Err(e) => Self {
+                order: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+pub struct SignedContractResult {
+    pub contract: Option<Contract>,
+    pub error: Option<String>,
+}
+
+impl From<Result<Contract>> for SignedContractResult {
+    fn from(res: Result<Contract>) -> Self {
+        match res {
+            Ok(contract) => Self {
+                contract: Some(contract),
+                error: None,
+            },
+            Err(e) => Self {
+                contract: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+// Batch signing, items are signed in parallel and the results keep the input order.
+impl Signer {
+    pub fn batch_sign_transfer(
+        &self,
+        txs: Vec<Transfer>,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    ) -> Vec<SignedTxResult> {
+        txs.into_par_iter()
+            .map(|tx| {
+                self.sign_transfer(tx, token_symbol.clone(), chain_id.clone(), addr.clone())
+                    .into()
+            })
+            .collect()
+    }
+
+    pub fn batch_create_signed_order(&self, orders: Vec<Order>) -> Vec<SignedOrderResult> {
+        orders
+            .into_par_iter()
+            .map(|order| self.create_signed_order(order).into())
+            .collect()
+    }
+
+    pub fn batch_create_signed_contract(
+        &self,
+        contracts: Vec<Contract>,
+    ) -> Vec<SignedContractResult> {
+        contracts
+            .into_par_iter()
+            .map(|contract| self.create_signed_contract(contract).into())
+            .collect()
     }
 }
 
@@ -265,11 +545,7 @@ impl ChangePubKey {
         eth_signature: Option<String>,
         ts: Option<u32>,
     ) -> Result<Self> {
-        let ts = if let Some(time_stamp) = ts {
-            time_stamp
-        } else {
-            UNIX_EPOCH.elapsed().unwrap().as_secs() as u32
-        };
+        let ts = resolve_ts(ts)?;
         let eth_signature = if let Some(s) = eth_signature {
             Some(PackedEthSignature::from_hex(&s)?)
         } else {
@@ -309,6 +585,42 @@ impl ChangePubKey {
         )
     }
 
+    /// The counterfactual CREATE2 address of the account for this tx's new pubkey hash.
+    #[frb(sync)]
+    pub fn get_create2_address(
+        &self,
+        creator_address: String,
+        salt_arg: String,
+        code_hash: String,
+    ) -> Result<String> {
+        let address = create2_address(
+            &ZkLinkAddress::from_hex(&creator_address)?,
+            &H256::from_str(&salt_arg)?,
+            &H256::from_str(&code_hash)?,
+            &self.inner.new_pk_hash,
+        )?;
+        Ok(format!("0x{}", hex::encode(address)))
+    }
+
+    /// Check the CREATE2 data derives the address of the account being activated.
+    #[frb(sync)]
+    pub fn check_create2_address(
+        &self,
+        creator_address: String,
+        salt_arg: String,
+        code_hash: String,
+        account_address: String,
+    ) -> Result<()> {
+        let address = self.get_create2_address(creator_address, salt_arg, code_hash)?;
+        ensure!(
+            ZkLinkAddress::from_hex(&address)? == ZkLinkAddress::from_hex(&account_address)?,
+            "create2 address {} does not match account address {}",
+            address,
+            account_address
+        );
+        Ok(())
+    }
+
     #[frb(sync)]
     pub fn set_eth_auth_data(&mut self, sig: String) -> Result<()> {
         let eth_signature = PackedEthSignature::from_hex(&sig)?;
@@ -317,9 +629,51 @@ impl ChangePubKey {
         Ok(())
     }
 
+    /// The starknet typed data to be signed by an external starknet account.
+    #[frb(sync)]
+    pub fn get_starknet_sign_msg(&self, chain_id: String, address: String) -> Result<String> {
+        let typed_data = self.inner.to_starknet_typed_data(&chain_id, &address)?;
+        Ok(to_string(&typed_data)?)
+    }
+
+    #[frb(sync)]
+    pub fn set_starknet_auth_data(&mut self, sig: String) -> Result<()> {
+        let signature = StarkEcdsaSignature::from_hex(&sig)?;
+        let stark_authdata = ChangePubKeyAuthData::StarkECDSA { signature };
+        self.inner.eth_auth_data = stark_authdata;
+        Ok(())
+    }
+
     tx_default!();
 }
 
+// keccak256(0xff ++ creator ++ keccak256(salt_arg ++ pubkey_hash) ++ code_hash)[12..]
+fn create2_address(
+    creator_address: &ZkLinkAddress,
+    salt_arg: &H256,
+    code_hash: &H256,
+    pubkey_hash: &PubKeyHash,
+) -> Result<[u8; 20]> {
+    let creator = creator_address.as_bytes();
+    ensure!(
+        creator.len() == 20,
+        "create2 creator must be a 20 bytes address"
+    );
+    let salt = Keccak256::new()
+        .chain_update(salt_arg.as_bytes())
+        .chain_update(pubkey_hash.data)
+        .finalize();
+    let hash = Keccak256::new()
+        .chain_update([0xff])
+        .chain_update(creator)
+        .chain_update(salt)
+        .chain_update(code_hash.as_bytes())
+        .finalize();
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    Ok(address)
+}
+
 #[frb(opaque)]
 pub struct Transfer {
     pub inner: InnerTransfer,
@@ -338,11 +692,7 @@ impl Transfer {
         nonce: u32,
         ts: Option<u32>,
     ) -> Result<Self> {
-        let ts = if let Some(time_stamp) = ts {
-            time_stamp
-        } else {
-            UNIX_EPOCH.elapsed().unwrap().as_secs() as u32
-        };
+        let ts = resolve_ts(ts)?;
         Ok(Self {
             inner: TransferBuilder {
                 account_id: account_id.into(),
@@ -370,6 +720,7 @@ impl Transfer {
 #[frb(opaque)]
 pub struct Withdraw {
     pub inner: InnerWithdraw,
+    call_data: Option<Vec<u8>>,
 }
 
 impl Withdraw {
@@ -389,11 +740,7 @@ impl Withdraw {
         withdraw_fee_ratio: u16,
         ts: Option<u32>,
     ) -> Result<Self> {
-        let ts = if let Some(time_stamp) = ts {
-            time_stamp
-        } else {
-            UNIX_EPOCH.elapsed().unwrap().as_secs() as u32
-        };
+        let ts = resolve_ts(ts)?;
         let data_hash = if let Some(data_hash) = call_data {
             Some(H256::from_str(&data_hash)?)
         } else {
@@ -416,15 +763,132 @@ impl Withdraw {
                 timestamp: ts.into(),
             }
             .build(),
+            call_data: None,
         })
     }
 
+    /// Attach the full call data, the tx only carries its hash. The data hash is part of the
+    /// signed bytes, so any previous signature is cleared and the tx has to be signed again.
+    #[frb(sync)]
+    pub fn set_call_data(&mut self, call_data: String) -> Result<()> {
+        let call_data = hex::decode(call_data.trim_start_matches("0x"))?;
+        self.inner.data_hash = Some(H256::from_slice(&Keccak256::digest(&call_data)));
+        self.inner.signature = Default::default();
+        self.call_data = Some(call_data);
+        Ok(())
+    }
+
+    #[frb(sync)]
+    pub fn get_call_data(&self) -> Option<String> {
+        self.call_data
+            .as_ref()
+            .map(|data| format!("0x{}", hex::encode(data)))
+    }
+
+    #[frb(sync)]
+    pub fn get_call_data_hash(&self) -> Option<String> {
+        self.inner
+            .data_hash
+            .map(|hash| format!("0x{}", hex::encode(hash.as_bytes())))
+    }
+
     #[frb(sync)]
     pub fn get_eth_sign_msg(&self, token_symbol: String) -> String {
         self.inner.get_eth_sign_msg(&token_symbol)
     }
 
-    tx_default!();
+    tx_default!(sign);
+
+    /// The tx json, with the call data preimage under `callData` when it is set.
+    #[frb(sync)]
+    pub fn to_json(&self) -> Result<String> {
+        let mut json = serde_json::to_value(&self.inner)?;
+        if let (Some(call_data), Some(fields)) = (self.get_call_data(), json.as_object_mut()) {
+            fields.insert("callData".to_string(), call_data.into());
+        }
+        Ok(to_string(&json)?)
+    }
+}
+
+const WITHDRAW_FEE_RATIO_DENOMINATOR: u16 = 10000;
+const L2_TOKEN_DECIMALS: u8 = 18;
+
+pub struct WithdrawQuote {
+    /// The amount received on layer1, in the layer1 token decimals.
+    pub l1_amount: String,
+    /// The part kept by the fast withdraw broker, in the layer1 token decimals.
+    pub broker_fee: String,
+    /// `amount` plus the zkLink tx fee, debited from the sub account.
+    pub total_cost: String,
+}
+
+/// Preview a withdraw. `amount` and `fee` use the layer2 precision, `withdraw_fee_ratio`
+/// is in basis points of `amount`, zero means no fast withdraw broker is involved.
+/// The amount is first truncated to the layer1 decimals, then the broker keeps its share of
+/// it and the received amount is rounded down.
+#[frb(sync)]
+pub fn quote_withdraw(
+    amount: String,
+    fee: String,
+    withdraw_fee_ratio: u16,
+    l1_decimals: u8,
+) -> Result<WithdrawQuote> {
+    ensure!(
+        withdraw_fee_ratio <= WITHDRAW_FEE_RATIO_DENOMINATOR,
+        "withdraw fee ratio {} exceeds {}",
+        withdraw_fee_ratio,
+        WITHDRAW_FEE_RATIO_DENOMINATOR
+    );
+    ensure!(
+        l1_decimals <= L2_TOKEN_DECIMALS,
+        "layer1 token decimals {} exceeds {}",
+        l1_decimals,
+        L2_TOKEN_DECIMALS
+    );
+    let amount = BigUint::from_str(&amount)?;
+    let fee = BigUint::from_str(&fee)?;
+    let l1_gross = &amount / BigUint::from(10u8).pow((L2_TOKEN_DECIMALS - l1_decimals) as u32);
+    let l1_amount = &l1_gross * BigUint::from(WITHDRAW_FEE_RATIO_DENOMINATOR - withdraw_fee_ratio)
+        / BigUint::from(WITHDRAW_FEE_RATIO_DENOMINATOR);
+    let broker_fee = &l1_gross - &l1_amount;
+    Ok(WithdrawQuote {
+        l1_amount: l1_amount.to_string(),
+        broker_fee: broker_fee.to_string(),
+        total_cost: (amount + fee).to_string(),
+    })
+}
+
+/// ABI encode a call, e.g. `encode_call_data("transfer(address,uint256)", ["0x..", "100"])`.
+#[frb(sync)]
+pub fn encode_call_data(function_signature: String, args: Vec<String>) -> Result<String> {
+    let function_signature = function_signature.trim();
+    let function = if function_signature.starts_with("function ") {
+        AbiParser::default().parse_function(function_signature)?
+    } else {
+        AbiParser::default().parse_function(&format!("function {function_signature}"))?
+    };
+    ensure!(
+        function.inputs.len() == args.len(),
+        "{} expects {} arguments, got {}",
+        function.name,
+        function.inputs.len(),
+        args.len()
+    );
+    let tokens = function
+        .inputs
+        .iter()
+        .zip(args.iter())
+        .map(|(param, arg)| LenientTokenizer::tokenize(&param.kind, arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let call_data = function.encode_input(&tokens)?;
+    Ok(format!("0x{}", hex::encode(call_data)))
+}
+
+/// The keccak256 hash of the call data, as stored in `Withdraw` data hash.
+#[frb(sync)]
+pub fn hash_call_data(call_data: String) -> Result<String> {
+    let call_data = hex::decode(call_data.trim_start_matches("0x"))?;
+    Ok(format!("0x{}", hex::encode(Keccak256::digest(call_data))))
 }
 
 #[frb(opaque)]
@@ -447,11 +911,7 @@ impl ForcedExit {
         withdraw_to_l1: bool,
         ts: Option<u32>,
     ) -> Result<Self> {
-        let ts = if let Some(time_stamp) = ts {
-            time_stamp
-        } else {
-            UNIX_EPOCH.elapsed().unwrap().as_secs() as u32
-        };
+        let ts = resolve_ts(ts)?;
         Ok(Self {
             inner: ForcedExitBuilder {
                 to_chain_id: to_chain_id.into(),
@@ -507,6 +967,62 @@ impl SpotPriceInfo {
     }
 }
 
+/// How the human readable prices and sizes of a pair map to protocol values.
+/// `tick_size` and `lot_size` are in protocol precision, zero disables the check.
+pub struct PairScale {
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    pub price_decimals: u8,
+    pub tick_size: String,
+    pub lot_size: String,
+}
+
+impl PairScale {
+    // a human price is quote per base, the protocol price is the raw quote per raw base
+    fn price_shift(&self) -> i32 {
+        self.price_decimals as i32 + self.quote_decimals as i32 - self.base_decimals as i32
+    }
+
+    fn steps(&self) -> Result<(num::BigUint, num::BigUint)> {
+        Ok((
+            num::BigUint::from_str(&self.tick_size)?,
+            num::BigUint::from_str(&self.lot_size)?,
+        ))
+    }
+}
+
+fn to_num(value: &BigUint) -> Result<num::BigUint> {
+    Ok(num::BigUint::from_str(&value.to_string())?)
+}
+
+/// Convert a human price such as "2531.75" to the protocol price, checking the tick size.
+#[frb(sync)]
+pub fn to_protocol_price(price: String, scale: PairScale) -> Result<String> {
+    let price = parse_decimal(&price, scale.price_shift())?;
+    check_step(&price, &scale.steps()?.0, "price")?;
+    Ok(price.to_string())
+}
+
+#[frb(sync)]
+pub fn from_protocol_price(price: String, scale: PairScale) -> Result<String> {
+    let price = num::BigUint::from_str(&price)?;
+    Ok(format_decimal(&price, scale.price_shift()))
+}
+
+/// Convert a human size of the base token to the protocol amount, checking the lot size.
+#[frb(sync)]
+pub fn to_protocol_size(size: String, scale: PairScale) -> Result<String> {
+    let size = parse_decimal(&size, scale.base_decimals as i32)?;
+    check_step(&size, &scale.steps()?.1, "size")?;
+    Ok(size.to_string())
+}
+
+#[frb(sync)]
+pub fn from_protocol_size(size: String, scale: PairScale) -> Result<String> {
+    let size = num::BigUint::from_str(&size)?;
+    Ok(format_decimal(&size, scale.base_decimals as i32))
+}
+
 #[frb(opaque)]
 pub struct Order {
     pub inner: InnerOrder,
@@ -545,6 +1061,14 @@ impl Order {
             },
         })
     }
+
+    /// Check the order price and amount follow the tick and lot size of the pair.
+    #[frb(sync)]
+    pub fn check_scale(&self, scale: PairScale) -> Result<()> {
+        let (tick_size, lot_size) = scale.steps()?;
+        check_step(&to_num(&self.inner.price)?, &tick_size, "price")?;
+        check_step(&to_num(&self.inner.amount)?, &lot_size, "amount")
+    }
 }
 
 #[frb(opaque)]
@@ -588,6 +1112,85 @@ impl OrderMatching {
     tx_default!();
 }
 
+pub struct OrderMatchingPreview {
+    pub base_amount: String,
+    pub quote_amount: String,
+    /// Negative when the maker is subsidized.
+    pub maker_fee: String,
+    pub taker_fee: String,
+    pub taker_remaining: String,
+    pub maker_remaining: String,
+}
+
+pub struct MatchedOrders {
+    pub tx: OrderMatching,
+    pub preview: OrderMatchingPreview,
+}
+
+impl OrderMatching {
+    /// Compute what matching the taker against the maker at the maker price would fill,
+    /// the order amounts are taken as the amounts left to fill.
+    #[frb(sync)]
+    pub fn preview(taker: &Order, maker: &Order) -> Result<OrderMatchingPreview> {
+        let fill = match_spot(&order_side(&taker.inner)?, &order_side(&maker.inner)?)?;
+        Ok(fill.into())
+    }
+
+    /// Build a ready to sign `OrderMatching` with the expected amounts filled in from `preview`.
+    #[frb(sync)]
+    pub fn from_orders(
+        account_id: u32,
+        sub_account_id: u8,
+        taker: Order,
+        maker: Order,
+        fee: String,
+        fee_token: u32,
+        contract_prices: Vec<ContractPrice>,
+        margin_prices: Vec<SpotPriceInfo>,
+    ) -> Result<MatchedOrders> {
+        let preview = Self::preview(&taker, &maker)?;
+        let tx = Self::new(
+            account_id,
+            sub_account_id,
+            taker,
+            maker,
+            fee,
+            fee_token,
+            contract_prices,
+            margin_prices,
+            preview.base_amount.clone(),
+            preview.quote_amount.clone(),
+        )?;
+        Ok(MatchedOrders { tx, preview })
+    }
+}
+
+impl From<SpotFill> for OrderMatchingPreview {
+    fn from(fill: SpotFill) -> Self {
+        Self {
+            base_amount: fill.base_amount.to_string(),
+            quote_amount: fill.quote_amount.to_string(),
+            maker_fee: fill.maker_fee.to_string(),
+            taker_fee: fill.taker_fee.to_string(),
+            taker_remaining: fill.taker_remaining.to_string(),
+            maker_remaining: fill.maker_remaining.to_string(),
+        }
+    }
+}
+
+fn order_side(order: &InnerOrder) -> Result<OrderSide> {
+    Ok(OrderSide {
+        base_token_id: *order.base_token_id,
+        quote_token_id: *order.quote_token_id,
+        amount: to_num(&order.amount)?,
+        price: to_num(&order.price)?,
+        is_sell: order.is_sell != 0,
+        maker_fee_rate: order.fee_rates[0],
+        taker_fee_rate: order.fee_rates[1],
+        has_subsidy: order.has_subsidy != 0,
+    })
+}
+
 #[frb(opaque)]
 pub struct Contract {
     pub inner: InnerContract,
@@ -625,6 +1228,14 @@ impl Contract {
             .build(),
         })
     }
+
+    /// Check the contract price and size follow the tick and lot size of the pair.
+    #[frb(sync)]
+    pub fn check_scale(&self, scale: PairScale) -> Result<()> {
+        let (tick_size, lot_size) = scale.steps()?;
+        check_step(&to_num(&self.inner.price)?, &tick_size, "price")?;
+        check_step(&to_num(&self.inner.size)?, &lot_size, "size")
+    }
 }
 
 #[frb(opaque)]
@@ -665,23 +1276,637 @@ impl ContractMatching {
     tx_default!();
 }
 
-#[frb(opaque)]
-pub struct AutoDeleveraging {
-    pub inner: InnerAutoDeleveraging,
+pub struct ContractMakerFill {
+    /// Index of the maker in the makers passed in.
+    pub maker_index: u32,
+    pub size: String,
+    pub quote_amount: String,
+    /// Negative when the maker is subsidized.
+    pub maker_fee: String,
+    pub taker_fee: String,
 }
 
-impl AutoDeleveraging {
+pub struct MatchedContracts {
+    pub tx: ContractMatching,
+    pub fills: Vec<ContractMakerFill>,
+    pub taker_fee: String,
+    /// Taker size left once the crossing makers are exhausted.
+    pub taker_remaining: String,
+}
+
+impl ContractMatching {
+    /// Walk `makers` in priority order and build a ready to sign `ContractMatching` with the
+    /// makers the taker fills against. Contract sizes are taken as the sizes left to fill.
     #[frb(sync)]
-    pub fn new(
+    pub fn from_contracts(
         account_id: u32,
         sub_account_id: u8,
-        sub_account_nonce: u32,
-        contract_prices: Vec<ContractPrice>,
-        margin_prices: Vec<SpotPriceInfo>,
-        adl_account_id: u32,
-        pair_id: u16,
-        adl_size: String,
-        adl_price: String,
+        taker: Contract,
+        makers: Vec<Contract>,
+        fee: String,
+        fee_token: u16,
+        contract_prices: Vec<ContractPrice>,
+        margin_prices: Vec<SpotPriceInfo>,
+    ) -> Result<MatchedContracts> {
+        let maker_sides = makers
+            .iter()
+            .map(|maker| contract_side(&maker.inner))
+            .collect::<Result<Vec<_>>>()?;
+        let result = match_contracts(&contract_side(&taker.inner)?, &maker_sides)?;
+        matched_contracts(
+            account_id,
+            sub_account_id,
+            taker,
+            makers,
+            result,
+            fee,
+            fee_token,
+            contract_prices,
+            margin_prices,
+        )
+    }
+}
+
+// build the `ContractMatching` of the makers with a fill, in fill order
+fn matched_contracts(
+    account_id: u32,
+    sub_account_id: u8,
+    taker: Contract,
+    makers: Vec<Contract>,
+    result: ContractFills,
+    fee: String,
+    fee_token: u16,
+    contract_prices: Vec<ContractPrice>,
+    margin_prices: Vec<SpotPriceInfo>,
+) -> Result<MatchedContracts> {
+    let mut makers = makers.into_iter().map(Some).collect::<Vec<_>>();
+    let filled_makers = result
+        .fills
+        .iter()
+        .filter_map(|fill| makers[fill.maker_index].take())
+        .collect();
+    let taker_fee: BigInt = result.fills.iter().map(|fill| &fill.taker_fee).sum();
+    let tx = ContractMatching::new(
+        account_id,
+        sub_account_id,
+        taker,
+        filled_makers,
+        fee,
+        fee_token,
+        contract_prices,
+        margin_prices,
+    )?;
+    Ok(MatchedContracts {
+        tx,
+        fills: result
+            .fills
+            .into_iter()
+            .map(|fill| ContractMakerFill {
+                maker_index: fill.maker_index as u32,
+                size: fill.size.to_string(),
+                quote_amount: fill.quote_amount.to_string(),
+                maker_fee: fill.maker_fee.to_string(),
+                taker_fee: fill.taker_fee.to_string(),
+            })
+            .collect(),
+        taker_fee: taker_fee.to_string(),
+        taker_remaining: result.taker_remaining.to_string(),
+    })
+}
+
+fn contract_side(contract: &InnerContract) -> Result<ContractSide> {
+    Ok(ContractSide {
+        pair_id: *contract.pair_id,
+        size: to_num(&contract.size)?,
+        price: to_num(&contract.price)?,
+        is_long: contract.direction != 0,
+        maker_fee_rate: contract.fee_rates[0],
+        taker_fee_rate: contract.fee_rates[1],
+        has_subsidy: contract.has_subsidy != 0,
+    })
+}
+
+/// Check the signature and that it comes from the layer2 key currently set for the account.
+fn verify_order_signature(
+    bytes: &[u8],
+    signature: &InnerZkLinkSignature,
+    account_id: u32,
+    pubkey_hashes: &HashMap<u32, PubKeyHash>,
+) -> Result<()> {
+    let Some(expected) = pubkey_hashes.get(&account_id) else {
+        anyhow::bail!("no pubkey hash set for account {account_id}");
+    };
+    ensure!(
+        signature.pub_key.public_key_hash() == *expected,
+        "order of account {account_id} is not signed by its pubkey hash"
+    );
+    ensure!(signature.verify_musig(bytes), "invalid order signature");
+    Ok(())
+}
+
+fn clone_prices(
+    contract_prices: &[ContractPrice],
+    margin_prices: &[SpotPriceInfo],
+) -> (Vec<ContractPrice>, Vec<SpotPriceInfo>) {
+    (
+        contract_prices
+            .iter()
+            .map(|e| ContractPrice {
+                inner: e.inner.clone(),
+            })
+            .collect(),
+        margin_prices
+            .iter()
+            .map(|e| SpotPriceInfo {
+                inner: e.inner.clone(),
+            })
+            .collect(),
+    )
+}
+
+pub struct SpotMatchResult {
+    /// One `OrderMatching` per maker the taker filled against, in priority order.
+    pub matches: Vec<MatchedOrders>,
+    /// Book id of the taker remainder left resting in the book.
+    pub resting_id: Option<u32>,
+}
+
+/// An in process price-time priority book of signed spot orders of one pair.
+/// Self-trade prevention is out of scope, the caller filters the taker's own orders.
+#[frb(opaque)]
+pub struct SpotOrderBook {
+    base_token_id: u32,
+    quote_token_id: u32,
+    book: Book<InnerOrder>,
+    pubkey_hashes: HashMap<u32, PubKeyHash>,
+}
+
+impl SpotOrderBook {
+    #[frb(sync)]
+    pub fn new(base_token_id: u32, quote_token_id: u32) -> Self {
+        Self {
+            base_token_id,
+            quote_token_id,
+            book: Book::default(),
+            pubkey_hashes: HashMap::new(),
+        }
+    }
+
+    /// Set the layer2 pubkey hash orders of `account_id` must be signed with,
+    /// as registered by its latest `ChangePubKey`.
+    #[frb(sync)]
+    pub fn set_pubkey_hash(&mut self, account_id: u32, pubkey_hash: String) -> Result<()> {
+        self.pubkey_hashes
+            .insert(account_id, PubKeyHash::from_hex(&pubkey_hash)?);
+        Ok(())
+    }
+
+    fn check_order(&self, order: &InnerOrder) -> Result<OrderSide> {
+        let side = order_side(order)?;
+        ensure!(
+            side.base_token_id == self.base_token_id && side.quote_token_id == self.quote_token_id,
+            "order is not for pair {}/{}",
+            self.base_token_id,
+            self.quote_token_id
+        );
+        ensure!(!side.amount.is_zero(), "order amount is zero");
+        verify_order_signature(
+            &order.get_bytes(),
+            &order.signature,
+            *order.account_id,
+            &self.pubkey_hashes,
+        )?;
+        Ok(side)
+    }
+
+    /// Add a signed order to the book without matching it, returns its book id.
+    #[frb(sync)]
+    pub fn insert(&mut self, order: Order) -> Result<u32> {
+        let side = self.check_order(&order.inner)?;
+        Ok(self
+            .book
+            .insert(side.price, !side.is_sell, side.amount, order.inner))
+    }
+
+    #[frb(sync)]
+    pub fn cancel(&mut self, id: u32) -> bool {
+        self.book.cancel(id).is_some()
+    }
+
+    #[frb(sync)]
+    pub fn len(&self) -> u32 {
+        self.book.len() as u32
+    }
+
+    #[frb(sync)]
+    pub fn is_empty(&self) -> bool {
+        self.book.len() == 0
+    }
+
+    #[frb(sync)]
+    pub fn best_bid(&self) -> Option<String> {
+        self.book.best_bid().map(|p| p.to_string())
+    }
+
+    #[frb(sync)]
+    pub fn best_ask(&self) -> Option<String> {
+        self.book.best_ask().map(|p| p.to_string())
+    }
+
+    /// Match a signed taker order against the book and rest what is left of it.
+    /// `account_id`, `sub_account_id`, `fee` and `fee_token` are those of the submitter of each tx.
+    #[frb(sync)]
+    pub fn match_order(
+        &mut self,
+        taker: Order,
+        account_id: u32,
+        sub_account_id: u8,
+        fee: String,
+        fee_token: u32,
+        contract_prices: Vec<ContractPrice>,
+        margin_prices: Vec<SpotPriceInfo>,
+    ) -> Result<SpotMatchResult> {
+        let mut taker_side = self.check_order(&taker.inner)?;
+        let mut matches = Vec::new();
+        let mut fills = Vec::new();
+        for id in self.book.crossing(!taker_side.is_sell, &taker_side.price) {
+            if taker_side.amount.is_zero() {
+                break;
+            }
+            let Some(entry) = self.book.get(id) else {
+                continue;
+            };
+            let mut maker_side = order_side(&entry.item)?;
+            maker_side.amount = entry.remaining.clone();
+            let maker = Order {
+                inner: entry.item.clone(),
+            };
+            let fill = match_spot(&taker_side, &maker_side)?;
+            let (cp, mp) = clone_prices(&contract_prices, &margin_prices);
+            let tx = OrderMatching::new(
+                account_id,
+                sub_account_id,
+                Order {
+                    inner: taker.inner.clone(),
+                },
+                maker,
+                fee.clone(),
+                fee_token,
+                cp,
+                mp,
+                fill.base_amount.to_string(),
+                fill.quote_amount.to_string(),
+            )?;
+            fills.push((id, fill.base_amount.clone()));
+            taker_side.amount = fill.taker_remaining.clone();
+            matches.push(MatchedOrders {
+                tx,
+                preview: fill.into(),
+            });
+        }
+        // the book is only touched once every tx is built, a failure leaves it as it was
+        for (id, amount) in fills {
+            self.book.fill(id, &amount);
+        }
+        let resting_id = if taker_side.amount.is_zero() {
+            None
+        } else {
+            Some(self.book.insert(
+                taker_side.price,
+                !taker_side.is_sell,
+                taker_side.amount,
+                taker.inner,
+            ))
+        };
+        Ok(SpotMatchResult {
+            matches,
+            resting_id,
+        })
+    }
+}
+
+enum SlotEntry {
+    Order(InnerOrder),
+    Contract(InnerContract),
+}
+
+/// A cancelled slot, see `OrderSlots`.
+pub struct SlotInvalidation {
+    pub slot_id: u32,
+    /// The nonce of the order dropped from the slot.
+    pub cancelled_nonce: u32,
+    /// The nonce the next order of the slot must be signed with. Once an order with this
+    /// nonce is filled the cancelled order can't be executed anymore.
+    pub next_nonce: u32,
+    /// The unsigned replacement of a cancelled order, the same order at `next_nonce`.
+    pub order: Option<Order>,
+    /// The unsigned replacement of a cancelled contract, the same contract at `next_nonce`.
+    pub contract: Option<Contract>,
+}
+
+impl From<Invalidated<SlotEntry>> for SlotInvalidation {
+    fn from(slot: Invalidated<SlotEntry>) -> Self {
+        let (order, contract) = match slot.item {
+            SlotEntry::Order(mut order) => {
+                order.nonce = slot.next_nonce.into();
+                order.signature = Default::default();
+                (Some(Order { inner: order }), None)
+            }
+            SlotEntry::Contract(mut contract) => {
+                contract.nonce = slot.next_nonce.into();
+                contract.signature = Default::default();
+                (None, Some(Contract { inner: contract }))
+            }
+        };
+        Self {
+            slot_id: slot.slot_id,
+            cancelled_nonce: slot.cancelled_nonce,
+            next_nonce: slot.next_nonce,
+            order,
+            contract,
+        }
+    }
+}
+
+/// Track the live order slots of a sub account.
+///
+/// zkLink has no cancel tx, a slot nonce only advances when an order in the slot is filled.
+/// Cancelling drops the order from the slot, reserves the next nonce and returns the order
+/// at that nonce. Until an order of the slot at `next_nonce` is filled any matcher holding
+/// the cancelled order can still fill it, so the wallet signs the replacement, or a smaller
+/// order of its own at `next_nonce`, and has it filled.
+#[frb(opaque)]
+pub struct OrderSlots {
+    account_id: u32,
+    sub_account_id: u8,
+    slots: Slots<SlotEntry>,
+}
+
+impl OrderSlots {
+    #[frb(sync)]
+    pub fn new(account_id: u32, sub_account_id: u8) -> Self {
+        Self {
+            account_id,
+            sub_account_id,
+            slots: Slots::default(),
+        }
+    }
+
+    fn track(
+        &mut self,
+        account_id: u32,
+        sub_account_id: u8,
+        slot_id: u32,
+        nonce: u32,
+        entry: SlotEntry,
+    ) -> Result<()> {
+        ensure!(
+            account_id == self.account_id && sub_account_id == self.sub_account_id,
+            "order belongs to another sub account"
+        );
+        self.slots.track(slot_id, nonce, entry)
+    }
+
+    #[frb(sync)]
+    pub fn track_order(&mut self, order: &Order) -> Result<()> {
+        let inner = &order.inner;
+        self.track(
+            *inner.account_id,
+            *inner.sub_account_id,
+            *inner.slot_id,
+            *inner.nonce,
+            SlotEntry::Order(inner.clone()),
+        )
+    }
+
+    #[frb(sync)]
+    pub fn track_contract(&mut self, contract: &Contract) -> Result<()> {
+        let inner = &contract.inner;
+        self.track(
+            *inner.account_id,
+            *inner.sub_account_id,
+            *inner.slot_id,
+            *inner.nonce,
+            SlotEntry::Contract(inner.clone()),
+        )
+    }
+
+    /// Forget a slot once its order is filled.
+    #[frb(sync)]
+    pub fn release(&mut self, slot_id: u32) -> bool {
+        self.slots.release(slot_id)
+    }
+
+    #[frb(sync)]
+    pub fn live_slots(&self) -> Vec<u32> {
+        self.slots.live_slots()
+    }
+
+    #[frb(sync)]
+    pub fn next_free_slot(&self) -> u32 {
+        self.slots.next_free_slot()
+    }
+
+    /// The lowest nonce the next order of `slot_id` may be signed with.
+    #[frb(sync)]
+    pub fn min_nonce(&self, slot_id: u32) -> u32 {
+        self.slots.min_nonce(slot_id)
+    }
+
+    #[frb(sync)]
+    pub fn cancel(&mut self, slot_id: u32) -> Result<SlotInvalidation> {
+        Ok(self.slots.cancel(slot_id)?.into())
+    }
+
+    /// Cancel every live slot, nothing is cancelled if one of them can't be.
+    #[frb(sync)]
+    pub fn cancel_all(&mut self) -> Result<Vec<SlotInvalidation>> {
+        Ok(self
+            .slots
+            .cancel_all()?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+pub struct ContractMatchResult {
+    pub matched: Option<MatchedContracts>,
+    /// Book id of the taker remainder left resting in the book.
+    pub resting_id: Option<u32>,
+}
+
+/// An in process price-time priority book of signed contracts of one pair.
+/// Self-trade prevention is out of scope, the caller filters the taker's own orders.
+#[frb(opaque)]
+pub struct ContractOrderBook {
+    pair_id: u16,
+    book: Book<InnerContract>,
+    pubkey_hashes: HashMap<u32, PubKeyHash>,
+}
+
+impl ContractOrderBook {
+    #[frb(sync)]
+    pub fn new(pair_id: u16) -> Self {
+        Self {
+            pair_id,
+            book: Book::default(),
+            pubkey_hashes: HashMap::new(),
+        }
+    }
+
+    /// Set the layer2 pubkey hash contracts of `account_id` must be signed with,
+    /// as registered by its latest `ChangePubKey`.
+    #[frb(sync)]
+    pub fn set_pubkey_hash(&mut self, account_id: u32, pubkey_hash: String) -> Result<()> {
+        self.pubkey_hashes
+            .insert(account_id, PubKeyHash::from_hex(&pubkey_hash)?);
+        Ok(())
+    }
+
+    fn check_contract(&self, contract: &InnerContract) -> Result<ContractSide> {
+        let side = contract_side(contract)?;
+        ensure!(
+            side.pair_id == self.pair_id,
+            "contract is not for pair {}",
+            self.pair_id
+        );
+        ensure!(!side.size.is_zero(), "contract size is zero");
+        verify_order_signature(
+            &contract.get_bytes(),
+            &contract.signature,
+            *contract.account_id,
+            &self.pubkey_hashes,
+        )?;
+        Ok(side)
+    }
+
+    /// Add a signed contract to the book without matching it, returns its book id.
+    #[frb(sync)]
+    pub fn insert(&mut self, contract: Contract) -> Result<u32> {
+        let side = self.check_contract(&contract.inner)?;
+        Ok(self
+            .book
+            .insert(side.price, side.is_long, side.size, contract.inner))
+    }
+
+    #[frb(sync)]
+    pub fn cancel(&mut self, id: u32) -> bool {
+        self.book.cancel(id).is_some()
+    }
+
+    #[frb(sync)]
+    pub fn len(&self) -> u32 {
+        self.book.len() as u32
+    }
+
+    #[frb(sync)]
+    pub fn is_empty(&self) -> bool {
+        self.book.len() == 0
+    }
+
+    #[frb(sync)]
+    pub fn best_bid(&self) -> Option<String> {
+        self.book.best_bid().map(|p| p.to_string())
+    }
+
+    #[frb(sync)]
+    pub fn best_ask(&self) -> Option<String> {
+        self.book.best_ask().map(|p| p.to_string())
+    }
+
+    /// Match a signed taker contract against the book into one `ContractMatching`,
+    /// and rest what is left of it.
+    #[frb(sync)]
+    pub fn match_contract(
+        &mut self,
+        taker: Contract,
+        account_id: u32,
+        sub_account_id: u8,
+        fee: String,
+        fee_token: u16,
+        contract_prices: Vec<ContractPrice>,
+        margin_prices: Vec<SpotPriceInfo>,
+    ) -> Result<ContractMatchResult> {
+        let taker_side = self.check_contract(&taker.inner)?;
+        let ids = self.book.crossing(taker_side.is_long, &taker_side.price);
+        let mut maker_ids = Vec::with_capacity(ids.len());
+        let mut makers = Vec::with_capacity(ids.len());
+        let mut maker_sides = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(entry) = self.book.get(id) else {
+                continue;
+            };
+            let mut side = contract_side(&entry.item)?;
+            side.size = entry.remaining.clone();
+            maker_ids.push(id);
+            maker_sides.push(side);
+            makers.push(Contract {
+                inner: entry.item.clone(),
+            });
+        }
+        let (matched, taker_remaining) = if maker_sides.is_empty() {
+            (None, taker_side.size.clone())
+        } else {
+            let result = match_contracts(&taker_side, &maker_sides)?;
+            let fills = result
+                .fills
+                .iter()
+                .map(|fill| (maker_ids[fill.maker_index], fill.size.clone()))
+                .collect::<Vec<_>>();
+            let taker_remaining = result.taker_remaining.clone();
+            let matched = matched_contracts(
+                account_id,
+                sub_account_id,
+                Contract {
+                    inner: taker.inner.clone(),
+                },
+                makers,
+                result,
+                fee,
+                fee_token,
+                contract_prices,
+                margin_prices,
+            )?;
+            for (id, size) in fills {
+                self.book.fill(id, &size);
+            }
+            (Some(matched), taker_remaining)
+        };
+        let resting_id = if taker_remaining.is_zero() {
+            None
+        } else {
+            Some(self.book.insert(
+                taker_side.price,
+                taker_side.is_long,
+                taker_remaining,
+                taker.inner,
+            ))
+        };
+        Ok(ContractMatchResult {
+            matched,
+            resting_id,
+        })
+    }
+}
+
+#[frb(opaque)]
+pub struct AutoDeleveraging {
+    pub inner: InnerAutoDeleveraging,
+}
+
+impl AutoDeleveraging {
+    #[frb(sync)]
+    pub fn new(
+        account_id: u32,
+        sub_account_id: u8,
+        sub_account_nonce: u32,
+        contract_prices: Vec<ContractPrice>,
+        margin_prices: Vec<SpotPriceInfo>,
+        adl_account_id: u32,
+        pair_id: u16,
+        adl_size: String,
+        adl_price: String,
         fee: String,
         fee_token: u16,
     ) -> Result<Self> {
@@ -798,6 +2023,37 @@ impl FundingInfo {
     }
 }
 
+pub struct FundingInfoValue {
+    pub pair_id: u16,
+    pub price: String,
+    pub funding_rate: i16,
+}
+
+/// The readable content of a `Parameter`, one variant per kind of global variable.
+pub enum ParameterInfo {
+    FeeAccount {
+        account_id: u32,
+    },
+    InsuranceFundAccount {
+        account_id: u32,
+    },
+    MarginInfo {
+        margin_id: u8,
+        symbol: String,
+        token_id: u32,
+        ratio: u8,
+    },
+    FundingInfos {
+        infos: Vec<FundingInfoValue>,
+    },
+    ContractInfo {
+        pair_id: u16,
+        symbol: String,
+        initial_margin_rate: u16,
+        maintenance_margin_rate: u16,
+    },
+}
+
 #[frb(opaque)]
 pub struct Parameter {
     pub inner: InnerParameter,
@@ -868,47 +2124,1408 @@ impl Parameter {
             },
         })
     }
-}
-
-#[frb(opaque)]
-pub struct UpdateGlobalVar {
-    pub inner: InnerUpdateGlobalVar,
-}
 
-impl UpdateGlobalVar {
     #[frb(sync)]
-    pub fn new(
-        from_chain_id: u8,
-        sub_account_id: u8,
-        parameter: Parameter,
-        serial_id: f64,
-    ) -> Result<Self> {
-        Ok(Self {
-            inner: UpdateGlobalVarBuilder {
-                from_chain_id: from_chain_id.into(),
-                sub_account_id: sub_account_id.into(),
-                parameter: parameter.inner,
-                serial_id: serial_id as u64,
+    pub fn info(&self) -> ParameterInfo {
+        match &self.inner {
+            InnerParameter::FeeAccount { account_id } => ParameterInfo::FeeAccount {
+                account_id: **account_id,
+            },
+            InnerParameter::InsuranceFundAccount { account_id } => {
+                ParameterInfo::InsuranceFundAccount {
+                    account_id: **account_id,
+                }
             }
-            .build(),
-        })
+            InnerParameter::MarginInfo {
+                margin_id,
+                symbol,
+                token_id,
+                ratio,
+            } => ParameterInfo::MarginInfo {
+                margin_id: **margin_id,
+                symbol: symbol.clone(),
+                token_id: **token_id,
+                ratio: *ratio,
+            },
+            InnerParameter::FundingInfos { infos } => ParameterInfo::FundingInfos {
+                infos: infos
+                    .iter()
+                    .map(|info| FundingInfoValue {
+                        pair_id: *info.pair_id,
+                        price: info.price.to_string(),
+                        funding_rate: info.funding_rate,
+                    })
+                    .collect(),
+            },
+            InnerParameter::ContractInfo {
+                pair_id,
+                symbol,
+                initial_margin_rate,
+                maintenance_margin_rate,
+            } => ParameterInfo::ContractInfo {
+                pair_id: **pair_id,
+                symbol: symbol.clone(),
+                initial_margin_rate: *initial_margin_rate,
+                maintenance_margin_rate: *maintenance_margin_rate,
+            },
+        }
     }
 
     #[frb(sync)]
     pub fn to_json(&self) -> Result<String> {
         Ok(to_string(&self.inner)?)
     }
+
+    #[frb(sync)]
+    pub fn from_json(json: String) -> Result<Self> {
+        Ok(Self {
+            inner: serde_json::from_str(&json)?,
+        })
+    }
 }
 
-#[frb(opaque)]
-pub struct EthTxOption {
-    pub inner: InnerEthTxOption,
+pub struct Position {
+    pub pair_id: u16,
+    pub is_long: bool,
+    pub size: String,
+    /// The open value of the position.
+    pub value: String,
 }
 
-impl EthTxOption {
-    #[frb(sync)]
-    pub fn new(
-        is_support_eip1559: bool,
+pub struct MarginBalance {
+    pub token_id: u32,
+    pub balance: String,
+}
+
+pub struct PositionRisk {
+    pub pair_id: u16,
+    pub notional: String,
+    pub unrealized_pnl: String,
+    pub initial_margin: String,
+    pub maintenance_margin: String,
+    pub liquidation_price: Option<String>,
+}
+
+pub struct AccountRisk {
+    pub collateral_value: String,
+    pub unrealized_pnl: String,
+    pub equity: String,
+    pub initial_margin: String,
+    pub maintenance_margin: String,
+    pub available_margin: String,
+    /// Equity over maintenance margin, per mille.
+    pub margin_ratio: Option<String>,
+    pub is_liquidatable: bool,
+    pub positions: Vec<PositionRisk>,
+}
+
+/// Compute the margin requirements and liquidation prices of a sub account.
+/// `parameters` are the `contract_info` and `margin_info` of the pairs and collaterals involved.
+#[frb(sync)]
+pub fn compute_account_risk(
+    positions: Vec<Position>,
+    balances: Vec<MarginBalance>,
+    parameters: Vec<Parameter>,
+    contract_prices: Vec<ContractPrice>,
+    margin_prices: Vec<SpotPriceInfo>,
+) -> Result<AccountRisk> {
+    let market = market_snapshot(&parameters, &contract_prices, &margin_prices)?;
+    let positions = position_snapshots(&positions)?;
+    let collaterals = collateral_snapshots(&balances)?;
+    let risk = margin::evaluate(&positions, &collaterals, &market)?;
+    Ok(AccountRisk {
+        collateral_value: risk.collateral_value.to_string(),
+        unrealized_pnl: risk.unrealized_pnl.to_string(),
+        equity: risk.equity.to_string(),
+        initial_margin: risk.initial_margin.to_string(),
+        maintenance_margin: risk.maintenance_margin.to_string(),
+        available_margin: risk.available_margin().to_string(),
+        margin_ratio: risk.margin_ratio().map(|r| r.to_string()),
+        is_liquidatable: risk.is_liquidatable(),
+        positions: risk
+            .positions
+            .into_iter()
+            .map(|p| PositionRisk {
+                pair_id: p.pair_id,
+                notional: p.notional.to_string(),
+                unrealized_pnl: p.unrealized_pnl.to_string(),
+                initial_margin: p.initial_margin.to_string(),
+                maintenance_margin: p.maintenance_margin.to_string(),
+                liquidation_price: p.liquidation_price.map(|p| p.to_string()),
+            })
+            .collect(),
+    })
+}
+
+fn collateral_snapshots(balances: &[MarginBalance]) -> Result<Vec<CollateralSnapshot>> {
+    balances
+        .iter()
+        .map(|b| {
+            Ok(CollateralSnapshot {
+                token_id: b.token_id,
+                balance: BigInt::from_str(&b.balance)?,
+            })
+        })
+        .collect()
+}
+
+fn position_snapshots(positions: &[Position]) -> Result<Vec<PositionSnapshot>> {
+    positions
+        .iter()
+        .map(|p| {
+            Ok(PositionSnapshot {
+                pair_id: p.pair_id,
+                is_long: p.is_long,
+                size: BigInt::from_str(&p.size)?,
+                value: BigInt::from_str(&p.value)?,
+            })
+        })
+        .collect()
+}
+
+fn market_snapshot(
+    parameters: &[Parameter],
+    contract_prices: &[ContractPrice],
+    margin_prices: &[SpotPriceInfo],
+) -> Result<MarketSnapshot> {
+    let mut market = MarketSnapshot::default();
+    for parameter in parameters {
+        match &parameter.inner {
+            InnerParameter::ContractInfo {
+                pair_id,
+                initial_margin_rate,
+                maintenance_margin_rate,
+                ..
+            } => {
+                market.contract_infos.insert(
+                    **pair_id,
+                    ContractRisk {
+                        initial_margin_rate: *initial_margin_rate,
+                        maintenance_margin_rate: *maintenance_margin_rate,
+                    },
+                );
+            }
+            InnerParameter::MarginInfo {
+                token_id, ratio, ..
+            } => {
+                market.collateral_ratios.insert(**token_id, *ratio);
+            }
+            _ => {}
+        }
+    }
+    market.contract_prices = contract_prices
+        .iter()
+        .map(|p| {
+            Ok((
+                *p.inner.pair_id,
+                BigInt::from_str(&p.inner.market_price.to_string())?,
+            ))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    market.spot_prices = margin_prices
+        .iter()
+        .map(|p| {
+            Ok((
+                *p.inner.token_id,
+                BigInt::from_str(&p.inner.price.to_string())?,
+            ))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    Ok(market)
+}
+
+pub struct AccountPositions {
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub positions: Vec<Position>,
+}
+
+pub struct FundingPayment {
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub pair_id: u16,
+    /// Positive when the account receives funding, negative when it pays.
+    pub amount: String,
+}
+
+/// Compute the funding settled for each account position, pairs without a `FundingInfo` are skipped.
+#[frb(sync)]
+pub fn compute_funding_payments(
+    accounts: Vec<AccountPositions>,
+    infos: Vec<FundingInfo>,
+) -> Result<Vec<FundingPayment>> {
+    let infos = funding_infos(&infos)?;
+    let mut payments = Vec::new();
+    for account in accounts {
+        for position in position_snapshots(&account.positions)? {
+            let Some((price, funding_rate)) = infos.get(&position.pair_id) else {
+                continue;
+            };
+            payments.push(FundingPayment {
+                account_id: account.account_id,
+                sub_account_id: account.sub_account_id,
+                pair_id: position.pair_id,
+                amount: funding_payment(&position, price, *funding_rate).to_string(),
+            });
+        }
+    }
+    Ok(payments)
+}
+
+pub struct FundingProjection {
+    pub pair_id: u16,
+    pub amount: String,
+    pub next_funding_ts: u32,
+}
+
+/// Project the next funding of the positions from the estimated price and funding rate,
+/// the next funding time comes from the configured time source.
+#[frb(sync)]
+pub fn project_funding(
+    positions: Vec<Position>,
+    estimates: Vec<FundingInfo>,
+    funding_interval: u32,
+) -> Result<Vec<FundingProjection>> {
+    ensure!(funding_interval > 0, "funding interval must be positive");
+    let estimates = funding_infos(&estimates)?;
+    let next_funding_ts = next_funding_ts(now_ts()?, funding_interval);
+    let mut projections = Vec::new();
+    for position in position_snapshots(&positions)? {
+        let Some((price, funding_rate)) = estimates.get(&position.pair_id) else {
+            continue;
+        };
+        projections.push(FundingProjection {
+            pair_id: position.pair_id,
+            amount: funding_payment(&position, price, *funding_rate).to_string(),
+            next_funding_ts,
+        });
+    }
+    Ok(projections)
+}
+
+fn funding_infos(infos: &[FundingInfo]) -> Result<HashMap<u16, (BigInt, i16)>> {
+    infos
+        .iter()
+        .map(|info| {
+            Ok((
+                *info.inner.pair_id,
+                (
+                    BigInt::from_str(&info.inner.price.to_string())?,
+                    info.inner.funding_rate,
+                ),
+            ))
+        })
+        .collect()
+}
+
+pub struct AccountSnapshot {
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub positions: Vec<Position>,
+    pub balances: Vec<MarginBalance>,
+}
+
+pub struct LiquidationCandidate {
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub equity: String,
+    pub maintenance_margin: String,
+    /// Equity over maintenance margin, per mille.
+    pub margin_ratio: Option<String>,
+}
+
+pub struct AdlCandidate {
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub pair_id: u16,
+    pub size: String,
+    pub unrealized_pnl: String,
+    /// Notional over equity, per mille.
+    pub leverage: String,
+    pub score: String,
+}
+
+fn account_snapshots(accounts: &[AccountSnapshot]) -> Result<Vec<InnerAccountSnapshot>> {
+    accounts
+        .iter()
+        .map(|a| {
+            Ok(InnerAccountSnapshot {
+                account_id: a.account_id,
+                sub_account_id: a.sub_account_id,
+                positions: position_snapshots(&a.positions)?,
+                collaterals: collateral_snapshots(&a.balances)?,
+            })
+        })
+        .collect()
+}
+
+/// Flag the accounts below their maintenance margin, the lowest margin ratio first.
+#[frb(sync)]
+pub fn scan_liquidations(
+    accounts: Vec<AccountSnapshot>,
+    parameters: Vec<Parameter>,
+    contract_prices: Vec<ContractPrice>,
+    margin_prices: Vec<SpotPriceInfo>,
+) -> Result<Vec<LiquidationCandidate>> {
+    let market = market_snapshot(&parameters, &contract_prices, &margin_prices)?;
+    let accounts = account_snapshots(&accounts)?;
+    Ok(liquidation::liquidatable(&accounts, &market)?
+        .into_iter()
+        .map(|(account, risk)| LiquidationCandidate {
+            account_id: account.account_id,
+            sub_account_id: account.sub_account_id,
+            equity: risk.equity.to_string(),
+            maintenance_margin: risk.maintenance_margin.to_string(),
+            margin_ratio: risk.margin_ratio().map(|r| r.to_string()),
+        })
+        .collect())
+}
+
+/// Rank the profitable positions on the `is_long` side of `pair_id` as auto deleveraging
+/// counterparties, by pnl ratio times leverage.
+#[frb(sync)]
+pub fn rank_adl_candidates(
+    accounts: Vec<AccountSnapshot>,
+    pair_id: u16,
+    is_long: bool,
+    parameters: Vec<Parameter>,
+    contract_prices: Vec<ContractPrice>,
+    margin_prices: Vec<SpotPriceInfo>,
+) -> Result<Vec<AdlCandidate>> {
+    let market = market_snapshot(&parameters, &contract_prices, &margin_prices)?;
+    let accounts = account_snapshots(&accounts)?;
+    Ok(liquidation::rank_adl(&accounts, pair_id, is_long, &market)?
+        .into_iter()
+        .map(|rank| AdlCandidate {
+            account_id: rank.account.account_id,
+            sub_account_id: rank.account.sub_account_id,
+            pair_id,
+            size: rank.position.size.to_string(),
+            unrealized_pnl: rank.unrealized_pnl.to_string(),
+            leverage: rank.leverage.to_string(),
+            score: rank.score.to_string(),
+        })
+        .collect())
+}
+
+/// Build the unsigned `Liquidation` txs of the candidates, using consecutive nonces
+/// of the submitter sub account from `sub_account_nonce`.
+#[frb(sync)]
+pub fn build_liquidations(
+    candidates: Vec<LiquidationCandidate>,
+    account_id: u32,
+    sub_account_id: u8,
+    sub_account_nonce: u32,
+    fee: String,
+    fee_token: u16,
+    contract_prices: Vec<ContractPrice>,
+    margin_prices: Vec<SpotPriceInfo>,
+) -> Result<Vec<Liquidation>> {
+    candidates
+        .iter()
+        .zip(sub_account_nonce..)
+        .map(|(candidate, nonce)| {
+            let (cp, mp) = clone_prices(&contract_prices, &margin_prices);
+            Liquidation::new(
+                account_id,
+                sub_account_id,
+                nonce,
+                cp,
+                mp,
+                candidate.account_id,
+                fee.clone(),
+                fee_token,
+            )
+        })
+        .collect()
+}
+
+/// Build the unsigned `AutoDeleveraging` txs closing `adl_size` at `adl_price` against the
+/// ranked candidates in order, using consecutive nonces from `sub_account_nonce`.
+#[frb(sync)]
+pub fn build_auto_deleveragings(
+    candidates: Vec<AdlCandidate>,
+    adl_size: String,
+    adl_price: String,
+    account_id: u32,
+    sub_account_id: u8,
+    sub_account_nonce: u32,
+    fee: String,
+    fee_token: u16,
+    contract_prices: Vec<ContractPrice>,
+    margin_prices: Vec<SpotPriceInfo>,
+) -> Result<Vec<AutoDeleveraging>> {
+    let sizes = candidates
+        .iter()
+        .map(|candidate| Ok(num::BigUint::from_str(&candidate.size)?))
+        .collect::<Result<Vec<_>>>()?;
+    let split = liquidation::split_adl(&sizes, &num::BigUint::from_str(&adl_size)?)?;
+    let mut txs = Vec::new();
+    for ((candidate, size), nonce) in candidates.iter().zip(split).zip(sub_account_nonce..) {
+        let (cp, mp) = clone_prices(&contract_prices, &margin_prices);
+        txs.push(AutoDeleveraging::new(
+            account_id,
+            sub_account_id,
+            nonce,
+            cp,
+            mp,
+            candidate.account_id,
+            candidate.pair_id,
+            size.to_string(),
+            adl_price.clone(),
+            fee.clone(),
+            fee_token,
+        )?);
+    }
+    Ok(txs)
+}
+
+#[frb(opaque)]
+pub struct UpdateGlobalVar {
+    pub inner: InnerUpdateGlobalVar,
+}
+
+impl UpdateGlobalVar {
+    #[frb(sync)]
+    pub fn new(
+        from_chain_id: u8,
+        sub_account_id: u8,
+        parameter: Parameter,
+        serial_id: f64,
+    ) -> Result<Self> {
+        Ok(Self {
+            inner: UpdateGlobalVarBuilder {
+                from_chain_id: from_chain_id.into(),
+                sub_account_id: sub_account_id.into(),
+                parameter: parameter.inner,
+                serial_id: serial_id as u64,
+            }
+            .build(),
+        })
+    }
+
+    #[frb(sync)]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(to_string(&self.inner)?)
+    }
+
+    #[frb(sync)]
+    pub fn from_json(json: String) -> Result<Self> {
+        Ok(Self {
+            inner: serde_json::from_str(&json)?,
+        })
+    }
+
+    #[frb(sync)]
+    pub fn get_bytes(&self) -> Vec<u8> {
+        self.inner.get_bytes()
+    }
+
+    #[frb(sync)]
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            inner: decode_update_global_var(&bytes)?,
+        })
+    }
+
+    #[frb(sync)]
+    pub fn tx_hash(&self) -> String {
+        InnerZkLinkTx::UpdateGlobalVar(self.inner.clone().into())
+            .hash()
+            .as_hex()
+    }
+
+    #[frb(sync)]
+    pub fn get_from_chain_id(&self) -> u8 {
+        *self.inner.from_chain_id
+    }
+
+    #[frb(sync)]
+    pub fn get_sub_account_id(&self) -> u8 {
+        *self.inner.sub_account_id
+    }
+
+    /// The serial id of the layer1 event this update originates from.
+    #[frb(sync)]
+    pub fn get_serial_id(&self) -> f64 {
+        self.inner.serial_id as f64
+    }
+
+    #[frb(sync)]
+    pub fn get_parameter(&self) -> Parameter {
+        Parameter {
+            inner: self.inner.parameter.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZkLinkTxType {
+    Deposit,
+    FullExit,
+    ChangePubKey,
+    Transfer,
+    Withdraw,
+    ForcedExit,
+    OrderMatching,
+    ContractMatching,
+    Liquidation,
+    AutoDeleveraging,
+    UpdateGlobalVar,
+    Funding,
+}
+
+#[frb(opaque)]
+pub struct ZkLinkTx {
+    pub inner: InnerZkLinkTx,
+    /// The call data preimage of a wrapped `Withdraw` data hash.
+    call_data: Option<Vec<u8>>,
+}
+
+impl ZkLinkTx {
+    #[frb(sync)]
+    pub fn from_json(json: String) -> Result<Self> {
+        Ok(Self {
+            inner: serde_json::from_str(&json)?,
+            call_data: None,
+        })
+    }
+
+    #[frb(sync)]
+    pub fn from_change_pubkey(tx: ChangePubKey) -> Self {
+        Self {
+            inner: InnerZkLinkTx::ChangePubKey(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_transfer(tx: Transfer) -> Self {
+        Self {
+            inner: InnerZkLinkTx::Transfer(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_withdraw(tx: Withdraw) -> Self {
+        Self {
+            inner: InnerZkLinkTx::Withdraw(tx.inner.into()),
+            call_data: tx.call_data,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_forced_exit(tx: ForcedExit) -> Self {
+        Self {
+            inner: InnerZkLinkTx::ForcedExit(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_order_matching(tx: OrderMatching) -> Self {
+        Self {
+            inner: InnerZkLinkTx::OrderMatching(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_contract_matching(tx: ContractMatching) -> Self {
+        Self {
+            inner: InnerZkLinkTx::ContractMatching(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_auto_deleveraging(tx: AutoDeleveraging) -> Self {
+        Self {
+            inner: InnerZkLinkTx::AutoDeleveraging(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_funding(tx: Funding) -> Self {
+        Self {
+            inner: InnerZkLinkTx::Funding(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_liquidation(tx: Liquidation) -> Self {
+        Self {
+            inner: InnerZkLinkTx::Liquidation(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_update_global_var(tx: UpdateGlobalVar) -> Self {
+        Self {
+            inner: InnerZkLinkTx::UpdateGlobalVar(tx.inner.into()),
+            call_data: None,
+        }
+    }
+
+    /// The call data of a wrapped `Withdraw`, when it was set with `Withdraw::set_call_data`.
+    #[frb(sync)]
+    pub fn get_call_data(&self) -> Option<String> {
+        self.call_data
+            .as_ref()
+            .map(|data| format!("0x{}", hex::encode(data)))
+    }
+
+    #[frb(sync)]
+    pub fn tx_type(&self) -> ZkLinkTxType {
+        match &self.inner {
+            InnerZkLinkTx::Deposit(_) => ZkLinkTxType::Deposit,
+            InnerZkLinkTx::FullExit(_) => ZkLinkTxType::FullExit,
+            InnerZkLinkTx::ChangePubKey(_) => ZkLinkTxType::ChangePubKey,
+            InnerZkLinkTx::Transfer(_) => ZkLinkTxType::Transfer,
+            InnerZkLinkTx::Withdraw(_) => ZkLinkTxType::Withdraw,
+            InnerZkLinkTx::ForcedExit(_) => ZkLinkTxType::ForcedExit,
+            InnerZkLinkTx::OrderMatching(_) => ZkLinkTxType::OrderMatching,
+            InnerZkLinkTx::ContractMatching(_) => ZkLinkTxType::ContractMatching,
+            InnerZkLinkTx::Liquidation(_) => ZkLinkTxType::Liquidation,
+            InnerZkLinkTx::AutoDeleveraging(_) => ZkLinkTxType::AutoDeleveraging,
+            InnerZkLinkTx::UpdateGlobalVar(_) => ZkLinkTxType::UpdateGlobalVar,
+            InnerZkLinkTx::Funding(_) => ZkLinkTxType::Funding,
+        }
+    }
+
+    /// Sign the wrapped tx with the zkLink key. Priority ops and `UpdateGlobalVar`
+    /// are submitted from layer1 and carry no zkLink signature.
+    #[frb(sync)]
+    pub fn sign(&mut self, zk_link_signer: ZkLinkSigner) -> Result<()> {
+        macro_rules! sign_inner {
+            ($tx:expr) => {
+                $tx.signature = zk_link_signer.inner.sign_musig(&$tx.get_bytes())?
+            };
+        }
+        let tx_type = self.tx_type();
+        match &mut self.inner {
+            InnerZkLinkTx::ChangePubKey(tx) => sign_inner!(tx),
+            InnerZkLinkTx::Transfer(tx) => sign_inner!(tx),
+            InnerZkLinkTx::Withdraw(tx) => sign_inner!(tx),
+            InnerZkLinkTx::ForcedExit(tx) => sign_inner!(tx),
+            InnerZkLinkTx::OrderMatching(tx) => sign_inner!(tx),
+            InnerZkLinkTx::ContractMatching(tx) => sign_inner!(tx),
+            InnerZkLinkTx::Liquidation(tx) => sign_inner!(tx),
+            InnerZkLinkTx::AutoDeleveraging(tx) => sign_inner!(tx),
+            InnerZkLinkTx::Funding(tx) => sign_inner!(tx),
+            _ => anyhow::bail!("{:?} tx can not be signed by zkLink signer", tx_type),
+        }
+        Ok(())
+    }
+
+    #[frb(sync)]
+    pub fn tx_hash(&self) -> String {
+        self.inner.hash().as_hex()
+    }
+
+    #[frb(sync)]
+    pub fn validate(&self) -> Result<()> {
+        match &self.inner {
+            InnerZkLinkTx::Deposit(tx) => tx.validate()?,
+            InnerZkLinkTx::FullExit(tx) => tx.validate()?,
+            InnerZkLinkTx::ChangePubKey(tx) => tx.validate()?,
+            InnerZkLinkTx::Transfer(tx) => tx.validate()?,
+            InnerZkLinkTx::Withdraw(tx) => tx.validate()?,
+            InnerZkLinkTx::ForcedExit(tx) => tx.validate()?,
+            InnerZkLinkTx::OrderMatching(tx) => tx.validate()?,
+            InnerZkLinkTx::ContractMatching(tx) => tx.validate()?,
+            InnerZkLinkTx::Liquidation(tx) => tx.validate()?,
+            InnerZkLinkTx::AutoDeleveraging(tx) => tx.validate()?,
+            InnerZkLinkTx::UpdateGlobalVar(tx) => tx.validate()?,
+            InnerZkLinkTx::Funding(tx) => tx.validate()?,
+        }
+        Ok(())
+    }
+
+    #[frb(sync)]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(to_string(&self.inner)?)
+    }
+}
+
+pub struct TokenInfo {
+    pub id: u32,
+    pub symbol: String,
+    /// The decimals of the token amounts on layer2.
+    pub decimals: u8,
+    /// The price of one token in a quote currency common to the registry, e.g. "1.0001".
+    /// Needed to compare a fee paid in another token than the amount.
+    pub price: Option<String>,
+}
+
+pub struct ChainInfo {
+    pub id: u8,
+    pub name: String,
+}
+
+/// The tokens and chains known to the app, used to make a tx readable before signing.
+pub struct TxRegistry {
+    pub tokens: Vec<TokenInfo>,
+    pub chains: Vec<ChainInfo>,
+    /// Warn when the fee exceeds this share of the amount, in basis points.
+    pub large_fee_ratio: u16,
+}
+
+const LARGE_FEE_RATIO_DENOMINATOR: u16 = 10000;
+/// Token prices of the registry are compared as integers with this many decimals.
+const REGISTRY_PRICE_DECIMALS: i32 = 18;
+
+pub struct AmountSummary {
+    pub token_id: u32,
+    /// None if the token is not in the registry.
+    pub symbol: Option<String>,
+    pub decimals: u8,
+    /// The raw amount as signed.
+    pub value: String,
+    /// `value` shifted by `decimals`, e.g. "1.5".
+    pub display: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SummaryWarning {
+    LargeFee,
+    /// The fee is paid in another token than the amount and one of them has no price.
+    FeeNotCompared,
+    UnknownToken {
+        token_id: u32,
+    },
+    UnknownChain {
+        chain_id: u8,
+    },
+    /// The receiver is the signer address, including transfers between own sub accounts.
+    SendToSelf,
+    /// The withdraw pays out to a layer1 address other than the signer address.
+    WithdrawToOtherAddress {
+        to_address: String,
+    },
+}
+
+pub struct TxSummary {
+    pub action: ZkLinkTxType,
+    pub from_account_id: u32,
+    pub from_sub_account_id: u8,
+    pub to: Option<String>,
+    pub amount: Option<AmountSummary>,
+    pub fee: Option<AmountSummary>,
+    pub chain_id: Option<u8>,
+    pub chain_name: Option<String>,
+    pub warnings: Vec<SummaryWarning>,
+}
+
+impl TxRegistry {
+    fn amount(
+        &self,
+        token_id: u32,
+        value: &BigUint,
+        warnings: &mut Vec<SummaryWarning>,
+    ) -> Result<AmountSummary> {
+        let token = self.tokens.iter().find(|t| t.id == token_id);
+        let warning = SummaryWarning::UnknownToken { token_id };
+        if token.is_none() && !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+        // zkLink keeps every token with 18 decimals on layer2, assume so for unknown tokens
+        let decimals = token.map_or(L2_TOKEN_DECIMALS, |t| t.decimals);
+        Ok(AmountSummary {
+            token_id,
+            symbol: token.map(|t| t.symbol.clone()),
+            decimals,
+            value: value.to_string(),
+            display: format_decimal(&to_num(value)?, decimals as i32),
+        })
+    }
+
+    fn chain(&self, chain_id: u8, warnings: &mut Vec<SummaryWarning>) -> Option<String> {
+        let name = self
+            .chains
+            .iter()
+            .find(|c| c.id == chain_id)
+            .map(|c| c.name.clone());
+        if name.is_none() {
+            warnings.push(SummaryWarning::UnknownChain { chain_id });
+        }
+        name
+    }
+
+    /// The value of `amount` in the registry quote currency, scaled by the decimals of both
+    /// the price and the token. None if the token has no price.
+    fn value(&self, amount: &AmountSummary) -> Result<Option<num::BigUint>> {
+        let Some(price) = self
+            .tokens
+            .iter()
+            .find(|t| t.id == amount.token_id)
+            .and_then(|t| t.price.as_ref())
+        else {
+            return Ok(None);
+        };
+        let price = parse_decimal(price, REGISTRY_PRICE_DECIMALS)?;
+        Ok(Some(num::BigUint::from_str(&amount.value)? * price))
+    }
+
+    /// None when the fee is in another token and can't be priced.
+    fn is_large_fee(&self, amount: &AmountSummary, fee: &AmountSummary) -> Result<Option<bool>> {
+        let (amount, fee) = if amount.token_id == fee.token_id {
+            (
+                num::BigUint::from_str(&amount.value)?,
+                num::BigUint::from_str(&fee.value)?,
+            )
+        } else {
+            let (Some(amount_value), Some(fee_value)) = (self.value(amount)?, self.value(fee)?)
+            else {
+                return Ok(None);
+            };
+            // bring both values to the same decimals
+            let ten = num::BigUint::from(10u8);
+            (
+                amount_value * ten.pow(fee.decimals as u32),
+                fee_value * ten.pow(amount.decimals as u32),
+            )
+        };
+        Ok(Some(
+            fee * LARGE_FEE_RATIO_DENOMINATOR > amount * self.large_fee_ratio,
+        ))
+    }
+}
+
+/// Build a readable summary of a `Transfer`, `Withdraw`, `ForcedExit`, `ChangePubKey` or
+/// `OrderMatching` for a confirmation screen. `signer_address` is the layer1 address of the
+/// signing account, used to flag sending to self and withdrawing to another address.
+#[frb(sync)]
+pub fn summarize_tx(
+    tx: &ZkLinkTx,
+    registry: TxRegistry,
+    signer_address: String,
+) -> Result<TxSummary> {
+    let signer_address = ZkLinkAddress::from_hex(&signer_address)?;
+    let mut warnings = Vec::new();
+    let mut summary = match &tx.inner {
+        InnerZkLinkTx::Transfer(tx) => {
+            if tx.to == signer_address {
+                warnings.push(SummaryWarning::SendToSelf);
+            }
+            TxSummary {
+                action: ZkLinkTxType::Transfer,
+                from_account_id: *tx.account_id,
+                from_sub_account_id: *tx.from_sub_account_id,
+                to: Some(tx.to.to_string()),
+                amount: Some(registry.amount(*tx.token, &tx.amount, &mut warnings)?),
+                fee: Some(registry.amount(*tx.token, &tx.fee, &mut warnings)?),
+                chain_id: None,
+                chain_name: None,
+                warnings: Vec::new(),
+            }
+        }
+        InnerZkLinkTx::Withdraw(tx) => {
+            if tx.to_address != signer_address {
+                warnings.push(SummaryWarning::WithdrawToOtherAddress {
+                    to_address: tx.to_address.to_string(),
+                });
+            }
+            TxSummary {
+                action: ZkLinkTxType::Withdraw,
+                from_account_id: *tx.account_id,
+                from_sub_account_id: *tx.sub_account_id,
+                to: Some(tx.to_address.to_string()),
+                amount: Some(registry.amount(*tx.l2_source_token, &tx.amount, &mut warnings)?),
+                fee: Some(registry.amount(*tx.l2_source_token, &tx.fee, &mut warnings)?),
+                chain_id: Some(*tx.to_chain_id),
+                chain_name: registry.chain(*tx.to_chain_id, &mut warnings),
+                warnings: Vec::new(),
+            }
+        }
+        InnerZkLinkTx::ForcedExit(tx) => {
+            if tx.target == signer_address {
+                warnings.push(SummaryWarning::SendToSelf);
+            }
+            TxSummary {
+                action: ZkLinkTxType::ForcedExit,
+                from_account_id: *tx.initiator_account_id,
+                from_sub_account_id: *tx.initiator_sub_account_id,
+                to: Some(tx.target.to_string()),
+                amount: Some(registry.amount(
+                    *tx.l2_source_token,
+                    &tx.exit_amount,
+                    &mut warnings,
+                )?),
+                fee: None,
+                chain_id: Some(*tx.to_chain_id),
+                chain_name: registry.chain(*tx.to_chain_id, &mut warnings),
+                warnings: Vec::new(),
+            }
+        }
+        InnerZkLinkTx::ChangePubKey(tx) => TxSummary {
+            action: ZkLinkTxType::ChangePubKey,
+            from_account_id: *tx.account_id,
+            from_sub_account_id: *tx.sub_account_id,
+            to: None,
+            amount: None,
+            fee: Some(registry.amount(*tx.fee_token, &tx.fee, &mut warnings)?),
+            chain_id: Some(*tx.chain_id),
+            chain_name: registry.chain(*tx.chain_id, &mut warnings),
+            warnings: Vec::new(),
+        },
+        InnerZkLinkTx::OrderMatching(tx) => TxSummary {
+            action: ZkLinkTxType::OrderMatching,
+            from_account_id: *tx.account_id,
+            from_sub_account_id: *tx.sub_account_id,
+            to: None,
+            amount: Some(registry.amount(
+                *tx.taker.base_token_id,
+                &tx.expect_base_amount,
+                &mut warnings,
+            )?),
+            fee: Some(registry.amount(*tx.fee_token, &tx.fee, &mut warnings)?),
+            chain_id: None,
+            chain_name: None,
+            warnings: Vec::new(),
+        },
+        _ => anyhow::bail!("{:?} tx has no summary", tx.tx_type()),
+    };
+    if let (Some(amount), Some(fee)) = (&summary.amount, &summary.fee) {
+        match registry.is_large_fee(amount, fee)? {
+            Some(true) => warnings.push(SummaryWarning::LargeFee),
+            Some(false) => {}
+            None => warnings.push(SummaryWarning::FeeNotCompared),
+        }
+    }
+    summary.warnings = warnings;
+    Ok(summary)
+}
+
+/// The layout written by `SignedTx::to_json`, the tx hash is derived again on load.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignedTxJson {
+    tx: InnerZkLinkTx,
+    #[serde(default)]
+    layer1_signature: Option<TxLayer1Signature>,
+}
+
+/// A signed tx together with its optional layer1 signature, as returned by `Signer`.
+#[frb(opaque)]
+pub struct SignedTx {
+    pub inner: TxSignature,
+}
+
+impl SignedTx {
+    #[frb(sync)]
+    pub fn tx(&self) -> ZkLinkTx {
+        ZkLinkTx {
+            inner: self.inner.tx.clone(),
+            call_data: None,
+        }
+    }
+
+    #[frb(sync)]
+    pub fn tx_type(&self) -> ZkLinkTxType {
+        self.tx().tx_type()
+    }
+
+    #[frb(sync)]
+    pub fn zklink_signature(&self) -> Option<ZkLinkSignature> {
+        let signature = match &self.inner.tx {
+            InnerZkLinkTx::ChangePubKey(tx) => &tx.signature,
+            InnerZkLinkTx::Transfer(tx) => &tx.signature,
+            InnerZkLinkTx::Withdraw(tx) => &tx.signature,
+            InnerZkLinkTx::ForcedExit(tx) => &tx.signature,
+            InnerZkLinkTx::OrderMatching(tx) => &tx.signature,
+            InnerZkLinkTx::ContractMatching(tx) => &tx.signature,
+            InnerZkLinkTx::Liquidation(tx) => &tx.signature,
+            InnerZkLinkTx::AutoDeleveraging(tx) => &tx.signature,
+            InnerZkLinkTx::Funding(tx) => &tx.signature,
+            _ => return None,
+        };
+        Some(ZkLinkSignature {
+            inner: signature.clone(),
+        })
+    }
+
+    /// The layer1 signature serialized as json, only present for txs that need one.
+    #[frb(sync)]
+    pub fn layer1_signature(&self) -> Result<Option<String>> {
+        let Some(sig) = &self.inner.layer1_signature else {
+            return Ok(None);
+        };
+        Ok(Some(to_string(sig)?))
+    }
+
+    #[frb(sync)]
+    pub fn tx_hash(&self) -> String {
+        self.inner.tx.hash().as_hex()
+    }
+
+    /// Replace the layer1 signature with an EIP-1271 signature from a smart contract wallet,
+    /// used for Transfer and Withdraw whose layer1 signer is not an EOA.
+    #[frb(sync)]
+    pub fn set_eip1271_signature(&mut self, sig: String) -> Result<()> {
+        let signature = EIP1271Signature(hex::decode(sig.trim_start_matches("0x"))?);
+        self.inner.layer1_signature = Some(TxLayer1Signature::EIP1271Signature(signature));
+        Ok(())
+    }
+
+    /// The `sendTransaction` json rpc request, ready to be posted to the zkLink node.
+    #[frb(sync)]
+    pub fn to_rpc_payload(&self) -> Result<String> {
+        let payload = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendTransaction",
+            "params": [self.inner.tx, self.inner.layer1_signature],
+        });
+        Ok(to_string(&payload)?)
+    }
+
+    #[frb(sync)]
+    pub fn to_json(&self) -> Result<String> {
+        let json = serde_json::json!({
+            "tx": self.inner.tx,
+            "layer1Signature": self.inner.layer1_signature,
+            "txHash": self.tx_hash(),
+        });
+        Ok(to_string(&json)?)
+    }
+
+    #[frb(sync)]
+    pub fn from_json(json: String) -> Result<Self> {
+        let signed: SignedTxJson = serde_json::from_str(&json)?;
+        Ok(Self {
+            inner: TxSignature {
+                tx: signed.tx,
+                layer1_signature: signed.layer1_signature,
+            },
+        })
+    }
+
+    #[frb(sync)]
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        to_cbor(&(&self.inner.tx, &self.inner.layer1_signature))
+    }
+
+    #[frb(sync)]
+    pub fn from_cbor(bytes: Vec<u8>) -> Result<Self> {
+        let (tx, layer1_signature) = from_cbor(&bytes)?;
+        Ok(Self {
+            inner: TxSignature {
+                tx,
+                layer1_signature,
+            },
+        })
+    }
+
+    /// The signature response of an air-gapped signer as QR code parts, see `AirGapRequest`.
+    #[frb(sync)]
+    pub fn to_qr_parts(&self, max_fragment_len: u32) -> Result<Vec<String>> {
+        encode_parts(
+            SIGNATURE_PART_TYPE,
+            &self.to_cbor()?,
+            max_fragment_len as usize,
+        )
+    }
+}
+
+const SIGN_REQUEST_PART_TYPE: &str = "sign-request";
+const SIGNATURE_PART_TYPE: &str = "signature";
+
+#[derive(Clone, Serialize, Deserialize)]
+enum AirGapTx {
+    Transfer {
+        tx: InnerTransfer,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    },
+    Withdraw {
+        tx: InnerWithdraw,
+        #[serde(default, with = "serde_bytes")]
+        call_data: Option<Vec<u8>>,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    },
+    ChangePubKey {
+        tx: InnerChangePubKey,
+    },
+}
+
+/// An unsigned tx passed from an online device to an offline signer. The online side shows
+/// `to_qr_parts`, the offline side scans them with `QrPartDecoder`, signs and shows the
+/// `SignedTx` parts back, which the online side checks with `verify`.
+#[frb(opaque)]
+pub struct AirGapRequest {
+    inner: AirGapTx,
+}
+
+impl AirGapRequest {
+    #[frb(sync)]
+    pub fn from_transfer(
+        tx: Transfer,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    ) -> Self {
+        Self {
+            inner: AirGapTx::Transfer {
+                tx: tx.inner,
+                token_symbol,
+                chain_id,
+                addr,
+            },
+        }
+    }
+
+    #[frb(sync)]
+    pub fn from_withdraw(
+        tx: Withdraw,
+        token_symbol: String,
+        chain_id: Option<String>,
+        addr: Option<String>,
+    ) -> Self {
+        Self {
+            inner: AirGapTx::Withdraw {
+                tx: tx.inner,
+                call_data: tx.call_data,
+                token_symbol,
+                chain_id,
+                addr,
+            },
+        }
+    }
+
+    /// The ChangePubKey is authorized with an EthECDSA signature of the offline signer.
+    #[frb(sync)]
+    pub fn from_change_pubkey(tx: ChangePubKey) -> Self {
+        Self {
+            inner: AirGapTx::ChangePubKey { tx: tx.inner },
+        }
+    }
+
+    /// The requested tx, e.g. to show `summarize_tx` on the offline device before signing.
+    #[frb(sync)]
+    pub fn tx(&self) -> ZkLinkTx {
+        let (inner, call_data) = match &self.inner {
+            AirGapTx::Transfer { tx, .. } => (InnerZkLinkTx::Transfer(tx.clone().into()), None),
+            AirGapTx::Withdraw { tx, call_data, .. } => (
+                InnerZkLinkTx::Withdraw(tx.clone().into()),
+                call_data.clone(),
+            ),
+            AirGapTx::ChangePubKey { tx } => (InnerZkLinkTx::ChangePubKey(tx.clone().into()), None),
+        };
+        ZkLinkTx { inner, call_data }
+    }
+
+    #[frb(sync)]
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        to_cbor(&self.inner)
+    }
+
+    /// Decode a request, a Withdraw whose call data doesn't hash to its `data_hash` is rejected
+    /// since the offline device would show call data that is not the one being signed.
+    #[frb(sync)]
+    pub fn from_cbor(bytes: Vec<u8>) -> Result<Self> {
+        let inner = from_cbor(&bytes)?;
+        if let AirGapTx::Withdraw {
+            tx,
+            call_data: Some(call_data),
+            ..
+        } = &inner
+        {
+            ensure!(
+                tx.data_hash == Some(H256::from_slice(&Keccak256::digest(call_data))),
+                "call data doesn't match the data hash of the withdraw"
+            );
+        }
+        Ok(Self { inner })
+    }
+
+    #[frb(sync)]
+    pub fn to_qr_parts(&self, max_fragment_len: u32) -> Result<Vec<String>> {
+        encode_parts(
+            SIGN_REQUEST_PART_TYPE,
+            &self.to_cbor()?,
+            max_fragment_len as usize,
+        )
+    }
+
+    /// Sign the request on the offline device.
+    #[frb(sync)]
+    pub fn sign(&self, signer: &Signer) -> Result<SignedTx> {
+        match self.inner.clone() {
+            AirGapTx::Transfer {
+                tx,
+                token_symbol,
+                chain_id,
+                addr,
+            } => signer.sign_transfer(Transfer { inner: tx }, token_symbol, chain_id, addr),
+            AirGapTx::Withdraw {
+                tx,
+                call_data,
+                token_symbol,
+                chain_id,
+                addr,
+            } => signer.sign_withdraw(
+                Withdraw {
+                    inner: tx,
+                    call_data,
+                },
+                token_symbol,
+                chain_id,
+                addr,
+            ),
+            AirGapTx::ChangePubKey { tx } => {
+                signer.sign_change_pubkey_with_eth_ecdsa_auth(ChangePubKey { inner: tx })
+            }
+        }
+    }
+
+    /// Check that the response signs exactly the requested tx with a valid zkLink signature,
+    /// for ChangePubKey the signing key must also be the new pubkey. The layer1 part, the
+    /// Ethereum signature of a Transfer or Withdraw and the EthECDSA auth of a ChangePubKey,
+    /// must be signed by `layer1_address`. Other layer1 signatures can't be checked offline
+    /// and are rejected.
+    #[frb(sync)]
+    pub fn verify(&self, signed: &SignedTx, layer1_address: String) -> Result<()> {
+        ensure!(
+            signed.tx_hash() == self.tx().tx_hash(),
+            "signed tx differs from the request"
+        );
+        let layer1_address = Address::from_str(&layer1_address)?;
+        let layer1_signature = match &signed.inner.layer1_signature {
+            Some(TxLayer1Signature::EthereumSignature(signature)) => Some(signature),
+            Some(_) => anyhow::bail!("only Ethereum layer1 signatures can be verified"),
+            None => None,
+        };
+        match (&self.inner, &signed.inner.tx) {
+            (AirGapTx::Transfer { token_symbol, .. }, InnerZkLinkTx::Transfer(tx)) => {
+                ensure!(
+                    tx.signature.verify_musig(&tx.get_bytes()),
+                    "invalid zkLink signature"
+                );
+                let Some(signature) = layer1_signature else {
+                    anyhow::bail!("missing layer1 signature");
+                };
+                verify_eth_signature(
+                    signature,
+                    tx.get_eth_sign_msg(token_symbol).as_bytes(),
+                    layer1_address,
+                )
+            }
+            (AirGapTx::Withdraw { token_symbol, .. }, InnerZkLinkTx::Withdraw(tx)) => {
+                ensure!(
+                    tx.signature.verify_musig(&tx.get_bytes()),
+                    "invalid zkLink signature"
+                );
+                let Some(signature) = layer1_signature else {
+                    anyhow::bail!("missing layer1 signature");
+                };
+                verify_eth_signature(
+                    signature,
+                    tx.get_eth_sign_msg(token_symbol).as_bytes(),
+                    layer1_address,
+                )
+            }
+            (AirGapTx::ChangePubKey { .. }, InnerZkLinkTx::ChangePubKey(tx)) => {
+                ensure!(
+                    tx.signature.verify_musig(&tx.get_bytes())
+                        && tx.signature.pub_key.public_key_hash() == tx.new_pk_hash,
+                    "invalid zkLink signature"
+                );
+                let ChangePubKeyAuthData::EthECDSA { eth_signature } = &tx.eth_auth_data else {
+                    anyhow::bail!("ChangePubKey is not authorized with EthECDSA");
+                };
+                let message =
+                    ChangePubKey { inner: tx.clone() }.get_eth_sign_msg(*tx.nonce, *tx.account_id);
+                verify_eth_signature(eth_signature, message.as_bytes(), layer1_address)
+            }
+            _ => anyhow::bail!("signed tx differs from the request"),
+        }
+    }
+}
+
+/// Check that a personal_sign signature of `message` was made by `address`.
+fn verify_eth_signature(
+    signature: &PackedEthSignature,
+    message: &[u8],
+    address: Address,
+) -> Result<()> {
+    let signature = ethers::types::Signature::try_from(&signature.serialize_packed()[..])?;
+    ensure!(
+        signature.recover(message)? == address,
+        "layer1 signature is not signed by {address:?}"
+    );
+    Ok(())
+}
+
+/// Reassemble an `AirGapRequest` or a `SignedTx` from scanned QR code parts.
+#[derive(Default)]
+#[frb(opaque)]
+pub struct QrPartDecoder {
+    inner: PartDecoder,
+}
+
+impl QrPartDecoder {
+    #[frb(sync)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one scanned part, parts can arrive in any order and repeat.
+    /// Returns true once every part has been received.
+    #[frb(sync)]
+    pub fn receive(&mut self, part: String) -> Result<bool> {
+        self.inner.receive(&part)
+    }
+
+    #[frb(sync)]
+    pub fn progress(&self) -> f64 {
+        self.inner.progress()
+    }
+
+    #[frb(sync)]
+    pub fn is_sign_request(&self) -> bool {
+        self.inner.part_type() == Some(SIGN_REQUEST_PART_TYPE)
+    }
+
+    #[frb(sync)]
+    pub fn sign_request(&self) -> Result<AirGapRequest> {
+        AirGapRequest::from_cbor(self.message(SIGN_REQUEST_PART_TYPE)?)
+    }
+
+    #[frb(sync)]
+    pub fn signed_tx(&self) -> Result<SignedTx> {
+        SignedTx::from_cbor(self.message(SIGNATURE_PART_TYPE)?)
+    }
+
+    fn message(&self, part_type: &str) -> Result<Vec<u8>> {
+        ensure!(
+            self.inner.part_type() == Some(part_type),
+            "scanned parts are not a {part_type}"
+        );
+        let Some(message) = self.inner.message() else {
+            anyhow::bail!("not every part has been received");
+        };
+        Ok(message.to_vec())
+    }
+}
+
+#[frb(opaque)]
+pub struct EthTxOption {
+    pub inner: InnerEthTxOption,
+    max_priority_fee: Option<BigUint>,
+}
+
+impl EthTxOption {
+    #[frb(sync)]
+    pub fn new(
+        is_support_eip1559: bool,
         to: String,
         nonce: Option<f64>,
         value: Option<String>,
@@ -934,42 +3551,244 @@ impl EthTxOption {
                 gas: gas.map(|g| g as u64),
                 gas_price,
             },
+            max_priority_fee: None,
+        })
+    }
+
+    /// The EIP-1559 priority fee, `gas_price` being the max fee. Defaults to 1.5 gwei, or the
+    /// max fee if lower. Only used by the external signer and the sign-only methods.
+    #[frb(sync)]
+    pub fn set_max_priority_fee(&mut self, max_priority_fee: String) -> Result<()> {
+        self.max_priority_fee = Some(BigUint::from_str(&max_priority_fee)?);
+        Ok(())
+    }
+}
+
+type SignL1TxFn = Arc<dyn Fn(String) -> DartFnFuture<String> + Send + Sync>;
+
+enum L1Signer {
+    Local(LocalWallet),
+    External {
+        address: Address,
+        sign_l1_tx: SignL1TxFn,
+    },
+}
+
+#[frb(opaque)]
+pub struct Wallet {
+    /// None when the layer1 txs are signed by an external signer.
+    pub inner: Option<InnerWallet>,
+    url: String,
+    l1_signer: L1Signer,
+}
+
+impl Wallet {
+    #[frb(sync)]
+    pub fn new(url: String, private_key: String) -> Result<Self> {
+        Ok(Self {
+            inner: Some(InnerWallet::new(&url, &private_key)),
+            url,
+            l1_signer: L1Signer::Local(LocalWallet::from_str(
+                private_key.trim_start_matches("0x"),
+            )?),
+        })
+    }
+
+    /// A wallet whose layer1 txs are signed outside the SDK, e.g. through WalletConnect.
+    /// `sign_l1_tx` receives the unsigned tx as `eth_signTransaction` json and returns the
+    /// raw signed tx, the wallet fills the missing nonce, gas and gas price and broadcasts it.
+    /// An empty string means the user rejected the tx, the call then fails without sending.
+    /// The signed tx must match the one handed to the signer in every field.
+    pub fn new_with_external_signer(
+        url: String,
+        address: String,
+        sign_l1_tx: impl Fn(String) -> DartFnFuture<String> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        Ok(Self {
+            inner: None,
+            url,
+            l1_signer: L1Signer::External {
+                address: Address::from_str(&address)?,
+                sign_l1_tx: Arc::new(sign_l1_tx),
+            },
         })
     }
-}
 
-#[frb(opaque)]
-pub struct Wallet {
-    pub inner: InnerWallet,
-}
+    fn provider(&self) -> Result<Provider<Http>> {
+        Ok(Provider::<Http>::try_from(self.url.as_str())?)
+    }
+
+    fn address(&self) -> Address {
+        match &self.l1_signer {
+            L1Signer::Local(wallet) => wallet.address(),
+            L1Signer::External { address, .. } => *address,
+        }
+    }
 
-impl Wallet {
     #[frb(sync)]
-    pub fn new(url: String, private_key: String) -> Result<Self> {
-        Ok(Self {
-            inner: InnerWallet::new(&url, &private_key),
-        })
+    pub fn get_address(&self) -> String {
+        format!("{:?}", self.address())
+    }
+
+    /// Fill the missing tx parameters from the node, have the external signer sign the tx
+    /// and broadcast it.
+    async fn send_external(&self, eth_params: EthTxOption, data: Vec<u8>) -> Result<H256> {
+        let L1Signer::External {
+            address,
+            sign_l1_tx,
+        } = &self.l1_signer
+        else {
+            anyhow::bail!("wallet has no external signer");
+        };
+        let provider = self.provider()?;
+        let max_priority_fee = eth_params
+            .max_priority_fee
+            .as_ref()
+            .map(to_u256)
+            .transpose()?;
+        let eth_params = eth_params.inner;
+        let nonce = match eth_params.nonce {
+            Some(nonce) => nonce.into(),
+            None => provider.get_transaction_count(*address, None).await?,
+        };
+        let gas_price = match &eth_params.gas_price {
+            Some(gas_price) => to_u256(gas_price)?,
+            None => provider.get_gas_price().await?,
+        };
+        let value = match &eth_params.value {
+            Some(value) => to_u256(value)?,
+            None => U256::zero(),
+        };
+        let params = L1TxParams {
+            chain_id: provider.get_chainid().await?.as_u64(),
+            to: Address::from_str(&eth_params.to.to_string())?,
+            value,
+            nonce,
+            gas: None,
+            gas_price,
+            max_priority_fee,
+            is_eip1559: eth_params.is_support_eip1559,
+        };
+        let mut tx = build_tx(params, data);
+        tx.set_from(*address);
+        let gas = match eth_params.gas {
+            Some(gas) => gas.into(),
+            None => provider.estimate_gas(&tx, None).await?,
+        };
+        tx.set_gas(gas);
+
+        let raw_tx = sign_l1_tx(to_string(&tx)?).await;
+        ensure!(!raw_tx.is_empty(), "the external signer rejected the tx");
+        let raw_tx = hex::decode(raw_tx.trim_start_matches("0x"))?;
+        let (signed, signer) = decode_signed(&raw_tx)?;
+        ensure!(
+            signer == *address,
+            "tx signed by {signer:?} instead of {address:?}"
+        );
+        ensure_same_tx(&tx, &signed)?;
+        let pending = provider.send_raw_transaction(raw_tx.into()).await?;
+        Ok(pending.tx_hash())
+    }
+
+    /// Call `isValidSignature(bytes32,bytes)` of an EIP-1271 wallet contract.
+    pub async fn is_valid_signature(
+        &self,
+        contract: String,
+        message_hash: String,
+        signature: String,
+    ) -> Result<bool> {
+        // the magic value is also the selector of `isValidSignature(bytes32,bytes)`
+        const EIP1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+        let provider = self.provider()?;
+        let message_hash = H256::from_str(&message_hash)?;
+        let signature = hex::decode(signature.trim_start_matches("0x"))?;
+        let mut data = EIP1271_MAGIC_VALUE.to_vec();
+        data.extend(encode(&[
+            Token::FixedBytes(message_hash.as_bytes().to_vec()),
+            Token::Bytes(signature),
+        ]));
+        let tx = TransactionRequest::new()
+            .to(Address::from_str(&contract)?)
+            .data(data);
+        let ret = provider.call(&tx.into(), None).await?;
+        Ok(ret.len() >= 4 && ret[..4] == EIP1271_MAGIC_VALUE)
     }
 
     pub async fn get_balance(&self) -> Result<String> {
-        let balance = self.inner.get_balance().await?;
-        Ok(balance.to_string())
+        let balance = match &self.inner {
+            Some(inner) => inner.get_balance().await?.to_string(),
+            None => self
+                .provider()?
+                .get_balance(self.address(), None)
+                .await?
+                .to_string(),
+        };
+        Ok(balance)
     }
 
     pub async fn get_nonce(&self, block_number: String) -> Result<f64> {
-        let nonce = self.inner.get_nonce(block_number).await?;
+        let nonce = match &self.inner {
+            Some(inner) => inner.get_nonce(block_number).await?,
+            None => {
+                let block = BlockNumber::from_str(&block_number).map_err(anyhow::Error::msg)?;
+                self.provider()?
+                    .get_transaction_count(self.address(), Some(block.into()))
+                    .await?
+            }
+        };
         Ok(nonce.as_u64() as f64)
     }
 
+    /// With an external signer `eth_params` must carry the gas, the gas price is read from
+    /// the node if missing.
     pub async fn get_deposit_fee(&self, eth_params: EthTxOption) -> Result<String> {
-        let fee = self.inner.get_fee(eth_params.inner).await?;
-        Ok(fee.to_string())
+        let fee = match &self.inner {
+            Some(inner) => inner.get_fee(eth_params.inner).await?.to_string(),
+            None => {
+                let Some(gas) = eth_params.inner.gas else {
+                    anyhow::bail!("the gas of the tx is needed to compute the fee");
+                };
+                let gas_price = match &eth_params.inner.gas_price {
+                    Some(gas_price) => to_u256(gas_price)?,
+                    None => self.provider()?.get_gas_price().await?,
+                };
+                (gas_price * gas).to_string()
+            }
+        };
+        Ok(fee)
     }
 
-    pub async fn wait_for_transaction(&self, tx_hash: String, timeout: Option<u32>) -> Result<u8> {
+    /// Wait until the tx is mined and has `confirmations` blocks on top of it, one by default.
+    /// `timeout` is in seconds. Deposits are only read from the logs of `zklink_contract`,
+    /// the receipt has none without it.
+    pub async fn wait_for_transaction(
+        &self,
+        tx_hash: String,
+        timeout: Option<u32>,
+        confirmations: Option<u32>,
+        zklink_contract: Option<String>,
+    ) -> Result<L1Receipt> {
         let tx_hash = H256::from_str(&tx_hash)?;
-        let status = self.inner.wait_for_transaction(tx_hash, timeout).await?;
-        Ok(status as u8)
+        let zklink_contract = zklink_contract
+            .map(|contract| Address::from_str(&contract))
+            .transpose()?;
+        let provider = self.provider()?;
+        let pending = PendingTransaction::new(tx_hash, &provider)
+            .confirmations(confirmations.unwrap_or(1) as usize);
+        let receipt = match timeout {
+            Some(secs) => tokio::time::timeout(Duration::from_secs(secs as u64), pending).await??,
+            None => pending.await?,
+        };
+        let Some(receipt) = receipt else {
+            anyhow::bail!("transaction {tx_hash:?} was dropped");
+        };
+        let confirmations = match receipt.block_number {
+            Some(block_number) => (provider.get_block_number().await? + 1)
+                .saturating_sub(block_number)
+                .as_u32(),
+            None => 0,
+        };
+        Ok(l1_receipt(receipt, confirmations, zklink_contract))
     }
 
     pub async fn approve_erc20(
@@ -978,15 +3797,24 @@ impl Wallet {
         amount: String,
         eth_params: EthTxOption,
     ) -> Result<String> {
-        let contract = ZkLinkAddress::from_hex(&contract)?;
-        let amount = BigUint::from_str(&amount)?;
-        let tx_hash = self
-            .inner
-            .approve_erc20(contract, amount, eth_params.inner)
-            .await?;
+        let tx_hash = match &self.inner {
+            Some(inner) => {
+                let contract = ZkLinkAddress::from_hex(&contract)?;
+                let amount = BigUint::from_str(&amount)?;
+                inner
+                    .approve_erc20(contract, amount, eth_params.inner)
+                    .await?
+            }
+            None => {
+                let data =
+                    approve_call(Address::from_str(&contract)?, U256::from_dec_str(&amount)?)?;
+                self.send_external(eth_params, data).await?
+            }
+        };
         Ok(hex::encode(tx_hash.as_bytes()))
     }
 
+    /// Gateway deposits are not supported with an external signer.
     pub async fn deposit_erc20(
         &self,
         sub_account_id: u8,
@@ -997,11 +3825,26 @@ impl Wallet {
         eth_params: EthTxOption,
         is_gateway: bool,
     ) -> Result<String> {
+        let Some(inner) = &self.inner else {
+            ensure!(
+                !is_gateway,
+                "gateway deposits need a wallet with a local key"
+            );
+            let data = deposit_erc20_call(
+                Address::from_str(&token_addr)?,
+                U256::from_dec_str(&amount)?,
+                ZkLinkAddress::from_hex(&deposit_to)?.as_bytes(),
+                sub_account_id,
+                mapping,
+            )?;
+            let tx_hash = self.send_external(eth_params, data).await?;
+            return Ok(hex::encode(tx_hash.as_bytes()));
+        };
         let deposit_to = ZkLinkAddress::from_hex(&deposit_to)?;
         let token_addr = ZkLinkAddress::from_hex(&token_addr)?;
         let amount = BigUint::from_str(&amount)?;
         let tx_hash = if !is_gateway {
-            self.inner
+            inner
                 .deposit_erc20_to_layer1(
                     sub_account_id,
                     deposit_to,
@@ -1012,7 +3855,7 @@ impl Wallet {
                 )
                 .await?
         } else {
-            self.inner
+            inner
                 .deposit_erc20_to_gateway(
                     sub_account_id,
                     deposit_to,
@@ -1026,6 +3869,8 @@ impl Wallet {
         Ok(hex::encode(tx_hash.as_bytes()))
     }
 
+    /// Gateway deposits are not supported with an external signer, which also needs the
+    /// deposited amount as `eth_params.value`.
     pub async fn deposit_eth(
         &self,
         sub_account_id: u8,
@@ -1033,13 +3878,29 @@ impl Wallet {
         eth_params: EthTxOption,
         is_gateway: bool,
     ) -> Result<String> {
+        let Some(inner) = &self.inner else {
+            ensure!(
+                !is_gateway,
+                "gateway deposits need a wallet with a local key"
+            );
+            ensure!(
+                eth_params.inner.value.is_some(),
+                "the deposited amount must be set as eth_params value"
+            );
+            let data = deposit_eth_call(
+                ZkLinkAddress::from_hex(&deposit_to)?.as_bytes(),
+                sub_account_id,
+            )?;
+            let tx_hash = self.send_external(eth_params, data).await?;
+            return Ok(hex::encode(tx_hash.as_bytes()));
+        };
         let deposit_to = ZkLinkAddress::from_hex(&deposit_to)?;
         let tx_hash = if !is_gateway {
-            self.inner
+            inner
                 .deposit_eth_to_layer1(sub_account_id, deposit_to, eth_params.inner)
                 .await?
         } else {
-            self.inner
+            inner
                 .deposit_eth_to_gateway(sub_account_id, deposit_to, eth_params.inner)
                 .await?
         };
@@ -1052,11 +3913,21 @@ impl Wallet {
         new_pubkey_hash: String,
         eth_params: EthTxOption,
     ) -> Result<String> {
-        let new_pubkey_hash = PubKeyHash::from_hex(&new_pubkey_hash)?;
-        let tx_hash = self
-            .inner
-            .set_auth_pubkey_hash(nonce as u64, new_pubkey_hash, eth_params.inner)
-            .await?;
+        let tx_hash = match &self.inner {
+            Some(inner) => {
+                let new_pubkey_hash = PubKeyHash::from_hex(&new_pubkey_hash)?;
+                inner
+                    .set_auth_pubkey_hash(nonce as u64, new_pubkey_hash, eth_params.inner)
+                    .await?
+            }
+            None => {
+                let data = set_auth_pubkey_hash_call(
+                    hex::decode(new_pubkey_hash.trim_start_matches("0x"))?,
+                    nonce as u32,
+                )?;
+                self.send_external(eth_params, data).await?
+            }
+        };
         Ok(hex::encode(tx_hash.as_bytes()))
     }
 
@@ -1068,21 +3939,920 @@ impl Wallet {
         mapping: bool,
         eth_params: EthTxOption,
     ) -> Result<String> {
-        let tx_hash = self
-            .inner
-            .full_exit(
-                account_id,
-                sub_account_id,
-                token_id,
-                mapping,
-                eth_params.inner,
-            )
-            .await?;
+        let tx_hash = match &self.inner {
+            Some(inner) => {
+                inner
+                    .full_exit(
+                        account_id,
+                        sub_account_id,
+                        token_id,
+                        mapping,
+                        eth_params.inner,
+                    )
+                    .await?
+            }
+            None => {
+                let data = full_exit_call(account_id, sub_account_id, token_id, mapping)?;
+                self.send_external(eth_params, data).await?
+            }
+        };
         Ok(hex::encode(tx_hash.as_bytes()))
     }
 }
 
+pub struct L1Log {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+/// A deposit decoded from the `NewPriorityRequest` event of the zkLink contract.
+pub struct DepositEvent {
+    /// The serial id of the priority request, also found in the layer2 Deposit tx.
+    pub serial_id: f64,
+    pub chain_id: u8,
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub token_id: u16,
+    pub target_token_id: u16,
+    pub amount: String,
+    /// The credited zkLink address, as bytes32.
+    pub target: String,
+}
+
+pub struct L1Receipt {
+    pub tx_hash: String,
+    /// 1 for success, 0 for a reverted tx, None for receipts of chains before Byzantium.
+    pub status: Option<u8>,
+    pub block_number: Option<f64>,
+    pub gas_used: Option<String>,
+    pub effective_gas_price: Option<String>,
+    pub confirmations: u32,
+    pub logs: Vec<L1Log>,
+    pub deposits: Vec<DepositEvent>,
+}
+
+// a log that fails to decode is skipped, it is still listed in `logs`
+fn l1_receipt(
+    receipt: TransactionReceipt,
+    confirmations: u32,
+    zklink_contract: Option<Address>,
+) -> L1Receipt {
+    let deposits = receipt
+        .logs
+        .iter()
+        .filter(|log| Some(log.address) == zklink_contract)
+        .filter_map(|log| decode_deposit(log).ok().flatten())
+        .map(|deposit| DepositEvent {
+            serial_id: deposit.serial_id as f64,
+            chain_id: deposit.chain_id,
+            account_id: deposit.account_id,
+            sub_account_id: deposit.sub_account_id,
+            token_id: deposit.token_id,
+            target_token_id: deposit.target_token_id,
+            amount: deposit.amount.to_string(),
+            target: format!("{:?}", deposit.owner),
+        })
+        .collect();
+    let logs = receipt
+        .logs
+        .iter()
+        .map(|log| L1Log {
+            address: format!("{:?}", log.address),
+            topics: log.topics.iter().map(|t| format!("{t:?}")).collect(),
+            data: format!("0x{}", hex::encode(&log.data)),
+        })
+        .collect();
+    L1Receipt {
+        tx_hash: hex::encode(receipt.transaction_hash.as_bytes()),
+        status: receipt.status.map(|status| status.as_u64() as u8),
+        block_number: receipt.block_number.map(|n| n.as_u64() as f64),
+        gas_used: receipt.gas_used.map(|g| g.to_string()),
+        effective_gas_price: receipt.effective_gas_price.map(|p| p.to_string()),
+        confirmations,
+        logs,
+        deposits,
+    }
+}
+
+pub struct SignedL1Tx {
+    /// The rlp encoded signed tx, ready for `eth_sendRawTransaction`.
+    pub raw_tx: String,
+    /// Encoded like the hashes returned by the broadcasting `Wallet` methods.
+    pub tx_hash: String,
+}
+
+fn to_u256(value: &BigUint) -> Result<U256> {
+    Ok(U256::from_dec_str(&value.to_string())?)
+}
+
+fn l1_tx_params(l1_chain_id: u32, eth_params: &EthTxOption) -> Result<L1TxParams> {
+    let max_priority_fee = eth_params
+        .max_priority_fee
+        .as_ref()
+        .map(to_u256)
+        .transpose()?;
+    let eth_params = &eth_params.inner;
+    let (Some(nonce), Some(gas), Some(gas_price)) =
+        (eth_params.nonce, eth_params.gas, &eth_params.gas_price)
+    else {
+        anyhow::bail!("offline signing needs the nonce, gas and gas price of the tx");
+    };
+    let value = match &eth_params.value {
+        Some(value) => to_u256(value)?,
+        None => U256::zero(),
+    };
+    Ok(L1TxParams {
+        chain_id: l1_chain_id as u64,
+        to: Address::from_str(&eth_params.to.to_string())?,
+        value,
+        nonce: nonce.into(),
+        gas: Some(gas.into()),
+        gas_price: to_u256(gas_price)?,
+        max_priority_fee,
+        is_eip1559: eth_params.is_support_eip1559,
+    })
+}
+
+// Sign-only counterparts that never touch the network, they need a wallet with a local key.
+// `eth_params` must carry the nonce, gas and gas price, `l1_chain_id` is the EIP-155 chain id
+// of the layer1.
+impl Wallet {
+    fn sign_l1_call(
+        &self,
+        l1_chain_id: u32,
+        eth_params: EthTxOption,
+        data: Vec<u8>,
+    ) -> Result<SignedL1Tx> {
+        let L1Signer::Local(wallet) = &self.l1_signer else {
+            anyhow::bail!("sign-only variants need a wallet with a local key");
+        };
+        let tx = build_tx(l1_tx_params(l1_chain_id, &eth_params)?, data);
+        let (raw_tx, tx_hash) = sign_tx(wallet, &tx)?;
+        Ok(SignedL1Tx {
+            raw_tx: format!("0x{}", hex::encode(raw_tx)),
+            tx_hash: hex::encode(tx_hash.as_bytes()),
+        })
+    }
+
+    /// `contract` is the spender, `eth_params.to` the ERC20 token.
+    #[frb(sync)]
+    pub fn sign_approve_erc20(
+        &self,
+        l1_chain_id: u32,
+        contract: String,
+        amount: String,
+        eth_params: EthTxOption,
+    ) -> Result<SignedL1Tx> {
+        let data = approve_call(Address::from_str(&contract)?, U256::from_dec_str(&amount)?)?;
+        self.sign_l1_call(l1_chain_id, eth_params, data)
+    }
+
+    /// `eth_params.to` is the zkLink contract, gateway deposits are not supported.
+    #[frb(sync)]
+    pub fn sign_deposit_erc20(
+        &self,
+        l1_chain_id: u32,
+        sub_account_id: u8,
+        deposit_to: String,
+        token_addr: String,
+        amount: String,
+        mapping: bool,
+        eth_params: EthTxOption,
+    ) -> Result<SignedL1Tx> {
+        let data = deposit_erc20_call(
+            Address::from_str(&token_addr)?,
+            U256::from_dec_str(&amount)?,
+            ZkLinkAddress::from_hex(&deposit_to)?.as_bytes(),
+            sub_account_id,
+            mapping,
+        )?;
+        self.sign_l1_call(l1_chain_id, eth_params, data)
+    }
+
+    /// The deposited amount is `eth_params.value`.
+    #[frb(sync)]
+    pub fn sign_deposit_eth(
+        &self,
+        l1_chain_id: u32,
+        sub_account_id: u8,
+        deposit_to: String,
+        eth_params: EthTxOption,
+    ) -> Result<SignedL1Tx> {
+        ensure!(
+            eth_params.inner.value.is_some(),
+            "the deposited amount must be set as eth_params value"
+        );
+        let data = deposit_eth_call(
+            ZkLinkAddress::from_hex(&deposit_to)?.as_bytes(),
+            sub_account_id,
+        )?;
+        self.sign_l1_call(l1_chain_id, eth_params, data)
+    }
+
+    #[frb(sync)]
+    pub fn sign_set_auth_pubkey_hash(
+        &self,
+        l1_chain_id: u32,
+        nonce: f64,
+        new_pubkey_hash: String,
+        eth_params: EthTxOption,
+    ) -> Result<SignedL1Tx> {
+        let data = set_auth_pubkey_hash_call(
+            hex::decode(new_pubkey_hash.trim_start_matches("0x"))?,
+            nonce as u32,
+        )?;
+        self.sign_l1_call(l1_chain_id, eth_params, data)
+    }
+
+    #[frb(sync)]
+    pub fn sign_full_exit(
+        &self,
+        l1_chain_id: u32,
+        account_id: u32,
+        sub_account_id: u8,
+        token_id: u16,
+        mapping: bool,
+        eth_params: EthTxOption,
+    ) -> Result<SignedL1Tx> {
+        let data = full_exit_call(account_id, sub_account_id, token_id, mapping)?;
+        self.sign_l1_call(l1_chain_id, eth_params, data)
+    }
+
+    /// Broadcast a tx signed by one of the sign-only methods, returns its hash.
+    pub async fn send_raw_transaction(&self, raw_tx: String) -> Result<String> {
+        let raw_tx = Bytes::from(hex::decode(raw_tx.trim_start_matches("0x"))?);
+        let pending = self.provider()?.send_raw_transaction(raw_tx).await?;
+        Ok(hex::encode(pending.tx_hash().as_bytes()))
+    }
+}
+
+pub struct ActivationRequest {
+    pub chain_id: u8,
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub new_pubkey_hash: String,
+    pub fee_token: u32,
+    pub fee: String,
+    pub nonce: u32,
+    pub ts: Option<u32>,
+}
+
+pub enum ActivationAuth {
+    EthEcdsa,
+    StarknetEcdsa,
+    /// `set_auth_pubkey_hash` is called for the ChangePubKey nonce of the request.
+    Onchain {
+        wallet: Wallet,
+        eth_params: EthTxOption,
+        timeout: Option<u32>,
+    },
+    Create2 {
+        creator_address: String,
+        salt_arg: String,
+        code_hash: String,
+    },
+}
+
+impl ActivationAuth {
+    fn kind(&self) -> &'static str {
+        match self {
+            ActivationAuth::EthEcdsa => "EthEcdsa",
+            ActivationAuth::StarknetEcdsa => "StarknetEcdsa",
+            ActivationAuth::Onchain { .. } => "Onchain",
+            ActivationAuth::Create2 { .. } => "Create2",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ActivationStage {
+    #[default]
+    Started,
+    L1AuthSent,
+    L1AuthConfirmed,
+    Signed,
+    Submitted,
+}
+
+pub struct ActivationProgress {
+    pub stage: ActivationStage,
+    /// Persist this and pass it back to `activate_account` to resume an interrupted activation.
+    pub checkpoint: String,
+}
+
+/// What a checkpoint was made for, a checkpoint only resumes the same activation.
+#[derive(PartialEq, Serialize, Deserialize)]
+struct ActivationKey {
+    account_id: u32,
+    sub_account_id: u8,
+    nonce: u32,
+    new_pubkey_hash: String,
+    auth: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ActivationState {
+    key: Option<ActivationKey>,
+    stage: ActivationStage,
+    ts: Option<u32>,
+    l1_tx_hash: Option<String>,
+    signed_tx: Option<String>,
+    tx_hash: Option<String>,
+}
+
+/// Run the whole ChangePubKey flow for the chosen auth mode and return the submitted tx hash.
+/// `submit` receives the json rpc payload of the signed tx and returns the tx hash from the node,
+/// or an empty string if the node rejected it.
+pub async fn activate_account(
+    signer: Signer,
+    request: ActivationRequest,
+    auth: ActivationAuth,
+    checkpoint: Option<String>,
+    submit: impl Fn(String) -> DartFnFuture<String>,
+    sink: StreamSink<ActivationProgress>,
+) -> Result<String> {
+    let key = ActivationKey {
+        account_id: request.account_id,
+        sub_account_id: request.sub_account_id,
+        nonce: request.nonce,
+        new_pubkey_hash: request.new_pubkey_hash.to_lowercase(),
+        auth: auth.kind().to_string(),
+    };
+    let mut state: ActivationState = if let Some(checkpoint) = checkpoint {
+        let state: ActivationState = serde_json::from_str(&checkpoint)?;
+        ensure!(
+            state.key.as_ref() == Some(&key),
+            "the checkpoint belongs to another activation"
+        );
+        state
+    } else {
+        ActivationState {
+            key: Some(key),
+            ..Default::default()
+        }
+    };
+    let report = |state: &ActivationState| -> Result<()> {
+        // the flow keeps going if the dart side stopped listening
+        let _ = sink.add(ActivationProgress {
+            stage: state.stage,
+            checkpoint: to_string(state)?,
+        });
+        Ok(())
+    };
+    report(&state)?;
+
+    let signed = if let Some(signed_tx) = &state.signed_tx {
+        SignedTx::from_json(signed_tx.clone())?
+    } else {
+        // the timestamp is part of the signed bytes, keep it stable across resumes
+        let ts = resolve_ts(state.ts.or(request.ts))?;
+        state.ts = Some(ts);
+        let tx = ChangePubKey::new(
+            request.chain_id,
+            request.account_id,
+            request.sub_account_id,
+            request.new_pubkey_hash.clone(),
+            request.fee_token,
+            request.fee,
+            request.nonce,
+            None,
+            Some(ts),
+        )?;
+        let signed = match auth {
+            ActivationAuth::EthEcdsa => signer.sign_change_pubkey_with_eth_ecdsa_auth(tx)?,
+            ActivationAuth::StarknetEcdsa => {
+                signer.sign_change_pubkey_with_starknet_ecdsa_auth(tx)?
+            }
+            ActivationAuth::Onchain {
+                wallet,
+                eth_params,
+                timeout,
+            } => {
+                if state.l1_tx_hash.is_none() {
+                    let l1_tx_hash = wallet
+                        .set_auth_pubkey_hash(
+                            request.nonce as f64,
+                            request.new_pubkey_hash,
+                            eth_params,
+                        )
+                        .await?;
+                    state.l1_tx_hash = Some(l1_tx_hash);
+                    state.stage = ActivationStage::L1AuthSent;
+                    report(&state)?;
+                }
+                if state.stage < ActivationStage::L1AuthConfirmed {
+                    let l1_tx_hash = state.l1_tx_hash.clone().unwrap_or_default();
+                    let receipt = wallet
+                        .wait_for_transaction(l1_tx_hash, timeout, None, None)
+                        .await?;
+                    ensure!(
+                        receipt.status == Some(1),
+                        "set_auth_pubkey_hash transaction failed"
+                    );
+                    state.stage = ActivationStage::L1AuthConfirmed;
+                    report(&state)?;
+                }
+                signer.sign_change_pubkey_with_onchain(tx)?
+            }
+            ActivationAuth::Create2 {
+                creator_address,
+                salt_arg,
+                code_hash,
+            } => signer.sign_change_pubkey_with_create2data_auth(
+                tx,
+                creator_address,
+                salt_arg,
+                code_hash,
+            )?,
+        };
+        state.signed_tx = Some(signed.to_json()?);
+        state.stage = ActivationStage::Signed;
+        report(&state)?;
+        signed
+    };
+
+    if let Some(tx_hash) = state.tx_hash {
+        return Ok(tx_hash);
+    }
+    let tx_hash = submit(signed.to_rpc_payload()?).await;
+    ensure!(!tx_hash.is_empty(), "the ChangePubKey was rejected");
+    state.tx_hash = Some(tx_hash.clone());
+    state.stage = ActivationStage::Submitted;
+    report(&state)?;
+    Ok(tx_hash)
+}
+
+#[derive(Clone, Debug)]
+pub enum TimeSource {
+    /// Read the device clock.
+    System,
+    /// Always return the given timestamp, useful for reproducible output.
+    Fixed { ts: u32 },
+    /// Read the device clock and shift it by the offset to the server time.
+    ServerOffset { offset_secs: i64 },
+}
+
+/// Configure the time source used to fill a missing `ts` in every tx constructor.
+/// Should be called once on startup, the system clock is used by default.
+#[frb(sync)]
+pub fn init_time_source(source: TimeSource) -> Result<()> {
+    set_time_source(source)
+}
+
+#[frb(sync)]
+pub fn current_timestamp() -> Result<u32> {
+    now_ts()
+}
+
 #[frb(init)]
 pub fn init_app() {
     flutter_rust_bridge::setup_default_user_utils();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> ZkLinkSigner {
+        ZkLinkSigner {
+            inner: InnerZkLinkSigner::new_from_seed(&[1u8; 32]).unwrap(),
+        }
+    }
+
+    fn withdraw() -> Withdraw {
+        Withdraw::new(
+            1,
+            0,
+            1,
+            "0x1111111111111111111111111111111111111111".to_string(),
+            18,
+            18,
+            "1000000".to_string(),
+            None,
+            "100".to_string(),
+            1,
+            true,
+            0,
+            Some(1_700_000_000),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn set_call_data_clears_the_signature() {
+        let pubkey = signer().get_pubkey();
+        let mut tx = withdraw();
+        tx.sign(signer()).unwrap();
+        assert_eq!(tx.inner.signature.pub_key.as_hex(), pubkey);
+        tx.set_call_data("0x1234".to_string()).unwrap();
+        assert_ne!(tx.inner.signature.pub_key.as_hex(), pubkey);
+        tx.sign(signer()).unwrap();
+        assert!(tx.inner.signature.verify_musig(&tx.inner.get_bytes()));
+    }
+
+    #[test]
+    fn call_data_hash_is_keccak_of_the_encoded_call() {
+        let call_data = encode_call_data(
+            "transfer(address,uint256)".to_string(),
+            vec![
+                "0x2222222222222222222222222222222222222222".to_string(),
+                "100".to_string(),
+            ],
+        )
+        .unwrap();
+        // the selector of `transfer(address,uint256)`
+        assert!(call_data.starts_with("0xa9059cbb"));
+        let mut tx = withdraw();
+        tx.set_call_data(call_data.clone()).unwrap();
+        assert_eq!(tx.get_call_data(), Some(call_data.clone()));
+        assert_eq!(
+            tx.get_call_data_hash(),
+            Some(hash_call_data(call_data).unwrap())
+        );
+        let json: serde_json::Value = serde_json::from_str(&tx.to_json().unwrap()).unwrap();
+        assert_eq!(json["callData"], tx.get_call_data().unwrap());
+    }
+
+    #[test]
+    fn hash_of_empty_call_data() {
+        assert_eq!(
+            hash_call_data("0x".to_string()).unwrap(),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn withdraw_fee_is_taken_after_truncating_to_layer1_decimals() {
+        let quote =
+            quote_withdraw("1234567890123456789".to_string(), "100".to_string(), 30, 6).unwrap();
+        // 1_234_567 * 9970 / 10000 rounded down, taking the fee on the 18 decimals
+        // amount first would give 1_230_864
+        assert_eq!(quote.l1_amount, "1230863");
+        assert_eq!(quote.broker_fee, "3704");
+        assert_eq!(quote.total_cost, "1234567890123456889");
+    }
+
+    #[test]
+    fn withdraw_without_broker() {
+        let quote =
+            quote_withdraw("1000000000000000000".to_string(), "0".to_string(), 0, 18).unwrap();
+        assert_eq!(quote.l1_amount, "1000000000000000000");
+        assert_eq!(quote.broker_fee, "0");
+    }
+
+    #[test]
+    fn withdraw_quote_rejects_out_of_range_inputs() {
+        assert!(quote_withdraw("1".to_string(), "0".to_string(), 10001, 18).is_err());
+        assert!(quote_withdraw("1".to_string(), "0".to_string(), 0, 19).is_err());
+    }
+
+    fn order(slot_id: u32, nonce: u32) -> Order {
+        Order::new(
+            1,
+            0,
+            slot_id,
+            nonce,
+            18,
+            17,
+            "1000".to_string(),
+            "2000000000000000000".to_string(),
+            true,
+            5,
+            10,
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn cancel_returns_the_order_at_the_next_nonce() {
+        let mut slots = OrderSlots::new(1, 0);
+        slots.track_order(&order(3, 7)).unwrap();
+        let cancelled = slots.cancel(3).unwrap();
+        assert_eq!((cancelled.cancelled_nonce, cancelled.next_nonce), (7, 8));
+        assert!(cancelled.contract.is_none());
+        let replacement = cancelled.order.unwrap().inner;
+        assert_eq!(*replacement.slot_id, 3);
+        assert_eq!(*replacement.nonce, 8);
+        assert_eq!(replacement.amount, order(3, 7).inner.amount);
+        assert!(slots.live_slots().is_empty());
+        assert!(slots.track_order(&order(3, 7)).is_err());
+        slots.track_order(&Order { inner: replacement }).unwrap();
+    }
+
+    #[test]
+    fn slots_of_another_sub_account_are_rejected() {
+        let mut slots = OrderSlots::new(1, 1);
+        assert!(slots.track_order(&order(0, 1)).is_err());
+    }
+
+    const SIGNER: &str = "0x1111111111111111111111111111111111111111";
+    const OTHER: &str = "0x2222222222222222222222222222222222222222";
+
+    fn registry() -> TxRegistry {
+        TxRegistry {
+            tokens: vec![
+                TokenInfo {
+                    id: 17,
+                    symbol: "USDT".to_string(),
+                    decimals: 18,
+                    price: Some("1".to_string()),
+                },
+                TokenInfo {
+                    id: 18,
+                    symbol: "ETH".to_string(),
+                    decimals: 18,
+                    price: Some("2000".to_string()),
+                },
+                TokenInfo {
+                    id: 19,
+                    symbol: "ZKL".to_string(),
+                    decimals: 18,
+                    price: None,
+                },
+            ],
+            chains: vec![ChainInfo {
+                id: 1,
+                name: "Ethereum".to_string(),
+            }],
+            large_fee_ratio: 100,
+        }
+    }
+
+    fn transfer(to: &str, token: u32, fee: &str, amount: &str) -> ZkLinkTx {
+        ZkLinkTx::from_transfer(
+            Transfer::new(
+                1,
+                to.to_string(),
+                0,
+                0,
+                token,
+                fee.to_string(),
+                amount.to_string(),
+                1,
+                Some(1_700_000_000),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn warnings(tx: &ZkLinkTx) -> Vec<SummaryWarning> {
+        summarize_tx(tx, registry(), SIGNER.to_string())
+            .unwrap()
+            .warnings
+    }
+
+    #[test]
+    fn summary_without_warnings() {
+        let summary = summarize_tx(
+            &transfer(OTHER, 17, "10", "1000000000000000000"),
+            registry(),
+            SIGNER.to_string(),
+        )
+        .unwrap();
+        assert!(summary.warnings.is_empty());
+        let amount = summary.amount.unwrap();
+        assert_eq!(amount.symbol.as_deref(), Some("USDT"));
+        assert_eq!(amount.display, "1");
+    }
+
+    #[test]
+    fn large_fee_warning() {
+        // 1% is the limit
+        assert!(warnings(&transfer(OTHER, 17, "10", "1000")).is_empty());
+        assert_eq!(
+            warnings(&transfer(OTHER, 17, "11", "1000")),
+            vec![SummaryWarning::LargeFee]
+        );
+    }
+
+    #[test]
+    fn send_to_self_warning() {
+        assert_eq!(
+            warnings(&transfer(SIGNER, 17, "0", "1000")),
+            vec![SummaryWarning::SendToSelf]
+        );
+    }
+
+    #[test]
+    fn withdraw_to_other_address_warning() {
+        let summary = summarize_tx(
+            &ZkLinkTx::from_withdraw(withdraw()),
+            registry(),
+            OTHER.to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            summary.warnings,
+            vec![SummaryWarning::WithdrawToOtherAddress {
+                to_address: ZkLinkAddress::from_hex(SIGNER).unwrap().to_string()
+            }]
+        );
+        assert_eq!(summary.chain_name.as_deref(), Some("Ethereum"));
+    }
+
+    #[test]
+    fn unknown_token_warning() {
+        let summary = summarize_tx(
+            &transfer(OTHER, 99, "0", "1000"),
+            registry(),
+            SIGNER.to_string(),
+        )
+        .unwrap();
+        // reported once for the amount and the fee
+        assert_eq!(
+            summary.warnings,
+            vec![SummaryWarning::UnknownToken { token_id: 99 }]
+        );
+        assert_eq!(summary.amount.unwrap().decimals, L2_TOKEN_DECIMALS);
+    }
+
+    fn amount(token_id: u32, value: &str) -> AmountSummary {
+        AmountSummary {
+            token_id,
+            symbol: None,
+            decimals: 18,
+            value: value.to_string(),
+            display: String::new(),
+        }
+    }
+
+    #[test]
+    fn large_fee_in_another_token_is_priced_through_the_registry() {
+        let registry = registry();
+        // 0.001 ETH is worth 2 USDT, 2% of 100 USDT
+        let fee = amount(18, "1000000000000000");
+        assert_eq!(
+            registry
+                .is_large_fee(&amount(17, "100000000000000000000"), &fee)
+                .unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            registry
+                .is_large_fee(&amount(17, "1000000000000000000000"), &fee)
+                .unwrap(),
+            Some(false)
+        );
+        // ZKL has no price
+        assert_eq!(
+            registry
+                .is_large_fee(&amount(17, "1000"), &amount(19, "1"))
+                .unwrap(),
+            None
+        );
+    }
+
+    const ETH_PRIVATE_KEY: &str =
+        "0xbe725250b123a39dab5b7579334d5888987c72a58f4508062545fe6e08ca94f4";
+
+    fn eth_address() -> String {
+        let wallet = LocalWallet::from_str(ETH_PRIVATE_KEY.trim_start_matches("0x")).unwrap();
+        format!("{:?}", wallet.address())
+    }
+
+    fn decode_request(parts: Vec<String>) -> Result<AirGapRequest> {
+        let mut decoder = QrPartDecoder::new();
+        for part in parts {
+            decoder.receive(part)?;
+        }
+        decoder.sign_request()
+    }
+
+    #[test]
+    fn air_gap_transfer_is_verified_against_the_layer1_address() {
+        let tx = Transfer::new(
+            1,
+            OTHER.to_string(),
+            0,
+            0,
+            17,
+            "10".to_string(),
+            "1000".to_string(),
+            1,
+            Some(1_700_000_000),
+        )
+        .unwrap();
+        let request = AirGapRequest::from_transfer(tx, "USDT".to_string(), None, None);
+        let received = decode_request(request.to_qr_parts(100).unwrap()).unwrap();
+        let signed = received
+            .sign(&Signer::eth_signer(ETH_PRIVATE_KEY.to_string()).unwrap())
+            .unwrap();
+        request.verify(&signed, eth_address()).unwrap();
+        assert!(request.verify(&signed, OTHER.to_string()).is_err());
+
+        // a signature over another token symbol is not the one shown to the user
+        let request = AirGapRequest {
+            inner: match request.inner {
+                AirGapTx::Transfer { tx, .. } => AirGapTx::Transfer {
+                    tx,
+                    token_symbol: "ETH".to_string(),
+                    chain_id: None,
+                    addr: None,
+                },
+                _ => unreachable!(),
+            },
+        };
+        assert!(request.verify(&signed, eth_address()).is_err());
+    }
+
+    #[test]
+    fn air_gap_withdraw_call_data_must_match_the_data_hash() {
+        let mut tx = withdraw();
+        tx.set_call_data("0x1234".to_string()).unwrap();
+        let request = AirGapRequest::from_withdraw(tx, "ETH".to_string(), None, None);
+        let received = decode_request(request.to_qr_parts(100).unwrap()).unwrap();
+        let signed = received
+            .sign(&Signer::eth_signer(ETH_PRIVATE_KEY.to_string()).unwrap())
+            .unwrap();
+        request.verify(&signed, eth_address()).unwrap();
+
+        let AirGapTx::Withdraw {
+            tx,
+            token_symbol,
+            chain_id,
+            addr,
+            ..
+        } = request.inner
+        else {
+            unreachable!()
+        };
+        let tampered = AirGapRequest {
+            inner: AirGapTx::Withdraw {
+                tx,
+                call_data: Some(vec![0x12, 0x35]),
+                token_symbol,
+                chain_id,
+                addr,
+            },
+        };
+        assert!(AirGapRequest::from_cbor(tampered.to_cbor().unwrap()).is_err());
+        assert!(decode_request(tampered.to_qr_parts(100).unwrap()).is_err());
+    }
+
+    #[test]
+    fn air_gap_change_pubkey_auth_is_verified() {
+        let signer = Signer::eth_signer(ETH_PRIVATE_KEY.to_string()).unwrap();
+        let change_pubkey = |pubkey_hash: String| {
+            ChangePubKey::new(
+                1,
+                1,
+                0,
+                pubkey_hash,
+                17,
+                "0".to_string(),
+                0,
+                None,
+                Some(1_700_000_000),
+            )
+            .unwrap()
+        };
+        // the new pubkey hash is the one of the layer2 key derived by the signer
+        let signed = signer
+            .sign_change_pubkey_with_eth_ecdsa_auth(change_pubkey(
+                "0x0000000000000000000000000000000000000000".to_string(),
+            ))
+            .unwrap();
+        let pubkey_hash = signed
+            .zklink_signature()
+            .unwrap()
+            .inner
+            .pub_key
+            .public_key_hash()
+            .as_hex();
+
+        let request = AirGapRequest::from_change_pubkey(change_pubkey(pubkey_hash));
+        let signed = request.sign(&signer).unwrap();
+        request.verify(&signed, eth_address()).unwrap();
+        assert!(request.verify(&signed, OTHER.to_string()).is_err());
+    }
+
+    #[test]
+    fn create2_address_vector() {
+        // derived independently with ethers `get_create2_address_from_hash`
+        let address = create2_address(
+            &ZkLinkAddress::from_hex("0x1111111111111111111111111111111111111111").unwrap(),
+            &H256::repeat_byte(0x22),
+            &H256::repeat_byte(0x44),
+            &PubKeyHash::from_hex("0x3333333333333333333333333333333333333333").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(address),
+            "b26ebcdacc92f0e3657a0ad86b23f6f298a5c376"
+        );
+    }
+
+    #[test]
+    fn create2_rejects_a_32_bytes_creator() {
+        let creator = ZkLinkAddress::from_hex(&format!("0x{}", "11".repeat(32))).unwrap();
+        assert!(create2_address(
+            &creator,
+            &H256::zero(),
+            &H256::zero(),
+            &PubKeyHash::from_hex("0x3333333333333333333333333333333333333333").unwrap(),
+        )
+        .is_err());
+    }
+}
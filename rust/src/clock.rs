@@ -0,0 +1,46 @@
+use crate::api::TimeSource;
+use anyhow::{anyhow, Result};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static TIME_SOURCE: RwLock<TimeSource> = RwLock::new(TimeSource::System);
+
+pub(crate) fn set_time_source(source: TimeSource) -> Result<()> {
+    let mut guard = TIME_SOURCE
+        .write()
+        .map_err(|_| anyhow!("time source lock poisoned"))?;
+    *guard = source;
+    Ok(())
+}
+
+/// Current timestamp in seconds, read from the configured time source.
+pub(crate) fn now_ts() -> Result<u32> {
+    let source = TIME_SOURCE
+        .read()
+        .map_err(|_| anyhow!("time source lock poisoned"))?
+        .clone();
+    match source {
+        TimeSource::System => system_ts(0),
+        TimeSource::Fixed { ts } => Ok(ts),
+        TimeSource::ServerOffset { offset_secs } => system_ts(offset_secs),
+    }
+}
+
+/// Use the caller supplied timestamp, or fall back to the configured time source.
+pub(crate) fn resolve_ts(ts: Option<u32>) -> Result<u32> {
+    match ts {
+        Some(ts) => Ok(ts),
+        None => now_ts(),
+    }
+}
+
+fn system_ts(offset_secs: i64) -> Result<u32> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| anyhow!("system clock is set before unix epoch"))?
+        .as_secs();
+    let secs = i64::try_from(secs)?
+        .checked_add(offset_secs)
+        .ok_or_else(|| anyhow!("timestamp overflow"))?;
+    u32::try_from(secs).map_err(|_| anyhow!("timestamp {secs} out of u32 range"))
+}
@@ -0,0 +1,93 @@
+use crate::margin::{price_precision, PositionSnapshot};
+use num::{BigInt, Integer, Signed};
+
+/// `funding_rate` of `FundingInfo` is a signed fixed point number with 6 decimals.
+pub(crate) const FUNDING_RATE_PRECISION: u32 = 1_000_000;
+
+/// The funding of one position, positive when the account receives it.
+/// Longs pay shorts when the rate is positive. The paying side is rounded up and
+/// the receiving side down, so the protocol never pays out more than it collects.
+/// `size * price * rate` is divided once so the notional is not rounded on its own.
+pub(crate) fn funding_payment(
+    position: &PositionSnapshot,
+    price: &BigInt,
+    funding_rate: i16,
+) -> BigInt {
+    let funding = &position.size * price * BigInt::from(funding_rate);
+    let pays = position.is_long == funding.is_positive();
+    let magnitude = funding.abs();
+    let precision = price_precision() * BigInt::from(FUNDING_RATE_PRECISION);
+    if pays {
+        -magnitude.div_ceil(&precision)
+    } else {
+        magnitude.div_floor(&precision)
+    }
+}
+
+/// The start of the next funding period after `now`.
+pub(crate) fn next_funding_ts(now: u32, interval: u32) -> u32 {
+    (now / interval).saturating_add(1).saturating_mul(interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(is_long: bool) -> PositionSnapshot {
+        PositionSnapshot {
+            pair_id: 1,
+            is_long,
+            size: BigInt::from(1_000_000_001u64),
+            value: BigInt::from(2_000_000_002u64),
+        }
+    }
+
+    fn payment(is_long: bool, funding_rate: i16) -> BigInt {
+        let price = BigInt::from(2) * price_precision();
+        funding_payment(&position(is_long), &price, funding_rate)
+    }
+
+    // notional 2_000_000_002 at 15 / 1_000_000 is 30_000.00003
+    #[test]
+    fn positive_rate_long_pays_short() {
+        assert_eq!(payment(true, 15), BigInt::from(-30001));
+        assert_eq!(payment(false, 15), BigInt::from(30000));
+    }
+
+    #[test]
+    fn negative_rate_short_pays_long() {
+        assert_eq!(payment(true, -15), BigInt::from(30000));
+        assert_eq!(payment(false, -15), BigInt::from(-30001));
+    }
+
+    #[test]
+    fn zero_rate_pays_nothing() {
+        assert_eq!(payment(true, 0), BigInt::from(0));
+        assert_eq!(payment(false, 0), BigInt::from(0));
+    }
+
+    // a notional of 1000.9 at 1%: rounding the notional first would charge 10 instead of 11
+    #[test]
+    fn notional_is_not_rounded_before_the_rate() {
+        let position = PositionSnapshot {
+            pair_id: 1,
+            is_long: true,
+            size: BigInt::from(10009),
+            value: BigInt::from(1001),
+        };
+        let price = price_precision() / BigInt::from(10);
+        assert_eq!(funding_payment(&position, &price, 10000), BigInt::from(-11));
+        let short = PositionSnapshot {
+            is_long: false,
+            ..position
+        };
+        assert_eq!(funding_payment(&short, &price, 10000), BigInt::from(10));
+    }
+
+    #[test]
+    fn next_funding_period() {
+        assert_eq!(next_funding_ts(0, 3600), 3600);
+        assert_eq!(next_funding_ts(3599, 3600), 3600);
+        assert_eq!(next_funding_ts(3600, 3600), 7200);
+    }
+}
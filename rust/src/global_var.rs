@@ -0,0 +1,261 @@
+use anyhow::{bail, ensure, Result};
+use zklink_sdk_types::basic_types::{BigUint, GetBytes};
+use zklink_sdk_types::tx_builder::UpdateGlobalVarBuilder;
+use zklink_sdk_types::tx_type::contract::{FundingInfo, Parameter, UpdateGlobalVar};
+
+/// Prices are packed on 120 bits in the tx bytes.
+const PRICE_BYTES: usize = 15;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(self.bytes.len() >= len, "unexpected end of bytes");
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn price(&mut self) -> Result<BigUint> {
+        Ok(BigUint::from_bytes_be(self.take(PRICE_BYTES)?))
+    }
+}
+
+// the parameter type byte follows the declaration order of `Parameter`
+fn read_parameter(reader: &mut Reader) -> Result<Parameter> {
+    let parameter = match reader.u8()? {
+        0 => Parameter::FeeAccount {
+            account_id: reader.u32()?.into(),
+        },
+        1 => Parameter::InsuranceFundAccount {
+            account_id: reader.u32()?.into(),
+        },
+        2 => Parameter::MarginInfo {
+            margin_id: reader.u8()?.into(),
+            symbol: String::new(),
+            token_id: reader.u32()?.into(),
+            ratio: reader.u8()?,
+        },
+        3 => {
+            let len = reader.u16()?;
+            let infos = (0..len)
+                .map(|_| {
+                    Ok(FundingInfo {
+                        pair_id: reader.u16()?.into(),
+                        price: reader.price()?,
+                        funding_rate: reader.i16()?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Parameter::FundingInfos { infos }
+        }
+        4 => Parameter::ContractInfo {
+            pair_id: reader.u16()?.into(),
+            symbol: String::new(),
+            initial_margin_rate: reader.u16()?,
+            maintenance_margin_rate: reader.u16()?,
+        },
+        t => bail!("unknown parameter type {t}"),
+    };
+    Ok(parameter)
+}
+
+/// Decode the bytes of an `UpdateGlobalVar`. Symbols are not part of the bytes and come back
+/// empty, the result is checked to encode back to the exact same bytes.
+pub(crate) fn decode_update_global_var(bytes: &[u8]) -> Result<UpdateGlobalVar> {
+    let mut reader = Reader { bytes };
+    let tx_type = reader.u8()?;
+    ensure!(
+        tx_type == UpdateGlobalVar::TX_TYPE,
+        "tx type {tx_type} is not UpdateGlobalVar"
+    );
+    let from_chain_id = reader.u8()?;
+    let sub_account_id = reader.u8()?;
+    let parameter = read_parameter(&mut reader)?;
+    let serial_id = reader.u64()?;
+    ensure!(
+        reader.bytes.is_empty(),
+        "trailing bytes after UpdateGlobalVar"
+    );
+    let tx = UpdateGlobalVarBuilder {
+        from_chain_id: from_chain_id.into(),
+        sub_account_id: sub_account_id.into(),
+        parameter,
+        serial_id,
+    }
+    .build();
+    ensure!(
+        tx.get_bytes() == bytes,
+        "bytes do not round trip as UpdateGlobalVar"
+    );
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(parameter: Parameter) -> Parameter {
+        let tx = UpdateGlobalVarBuilder {
+            from_chain_id: 1u8.into(),
+            sub_account_id: 2u8.into(),
+            parameter,
+            serial_id: 1234,
+        }
+        .build();
+        let bytes = tx.get_bytes();
+        let decoded = decode_update_global_var(&bytes).unwrap();
+        assert_eq!(decoded.get_bytes(), bytes);
+        assert_eq!(*decoded.from_chain_id, 1);
+        assert_eq!(*decoded.sub_account_id, 2);
+        assert_eq!(decoded.serial_id, 1234);
+        decoded.parameter
+    }
+
+    #[test]
+    fn fee_account() {
+        let Parameter::FeeAccount { account_id } = round_trip(Parameter::FeeAccount {
+            account_id: 10u32.into(),
+        }) else {
+            panic!("expected FeeAccount");
+        };
+        assert_eq!(*account_id, 10);
+    }
+
+    #[test]
+    fn insurance_fund_account() {
+        let Parameter::InsuranceFundAccount { account_id } =
+            round_trip(Parameter::InsuranceFundAccount {
+                account_id: 11u32.into(),
+            })
+        else {
+            panic!("expected InsuranceFundAccount");
+        };
+        assert_eq!(*account_id, 11);
+    }
+
+    #[test]
+    fn margin_info() {
+        let Parameter::MarginInfo {
+            margin_id,
+            symbol,
+            token_id,
+            ratio,
+        } = round_trip(Parameter::MarginInfo {
+            margin_id: 3u8.into(),
+            symbol: "USDT".to_string(),
+            token_id: 17u32.into(),
+            ratio: 95,
+        })
+        else {
+            panic!("expected MarginInfo");
+        };
+        assert_eq!(*margin_id, 3);
+        // symbols are not part of the bytes
+        assert!(symbol.is_empty());
+        assert_eq!(*token_id, 17);
+        assert_eq!(ratio, 95);
+    }
+
+    #[test]
+    fn funding_infos() {
+        let infos = vec![
+            FundingInfo {
+                pair_id: 1u16.into(),
+                price: BigUint::from(30_000u64) * BigUint::from(10u8).pow(18),
+                funding_rate: 15,
+            },
+            FundingInfo {
+                pair_id: 2u16.into(),
+                price: BigUint::from(1u8),
+                funding_rate: -7,
+            },
+        ];
+        let Parameter::FundingInfos { infos: decoded } = round_trip(Parameter::FundingInfos {
+            infos: infos.clone(),
+        }) else {
+            panic!("expected FundingInfos");
+        };
+        assert_eq!(decoded.len(), infos.len());
+        for (decoded, info) in decoded.iter().zip(&infos) {
+            assert_eq!(decoded.pair_id, info.pair_id);
+            assert_eq!(decoded.price, info.price);
+            assert_eq!(decoded.funding_rate, info.funding_rate);
+        }
+    }
+
+    #[test]
+    fn empty_funding_infos() {
+        let Parameter::FundingInfos { infos } =
+            round_trip(Parameter::FundingInfos { infos: vec![] })
+        else {
+            panic!("expected FundingInfos");
+        };
+        assert!(infos.is_empty());
+    }
+
+    #[test]
+    fn contract_info() {
+        let Parameter::ContractInfo {
+            pair_id,
+            symbol,
+            initial_margin_rate,
+            maintenance_margin_rate,
+        } = round_trip(Parameter::ContractInfo {
+            pair_id: 4u16.into(),
+            symbol: "BTCUSDT".to_string(),
+            initial_margin_rate: 100,
+            maintenance_margin_rate: 50,
+        })
+        else {
+            panic!("expected ContractInfo");
+        };
+        assert_eq!(*pair_id, 4);
+        assert!(symbol.is_empty());
+        assert_eq!(initial_margin_rate, 100);
+        assert_eq!(maintenance_margin_rate, 50);
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        let tx = UpdateGlobalVarBuilder {
+            from_chain_id: 1u8.into(),
+            sub_account_id: 0u8.into(),
+            parameter: Parameter::FeeAccount {
+                account_id: 10u32.into(),
+            },
+            serial_id: 1,
+        }
+        .build();
+        let bytes = tx.get_bytes();
+        assert!(decode_update_global_var(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode_update_global_var(&trailing).is_err());
+        let mut unknown = bytes;
+        unknown[3] = 0xff;
+        assert!(decode_update_global_var(&unknown).is_err());
+    }
+}
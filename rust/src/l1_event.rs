@@ -0,0 +1,143 @@
+use anyhow::{ensure, Result};
+use ethers::abi::{decode, ParamType};
+use ethers::types::{Log, H256};
+use sha3::{Digest, Keccak256};
+
+const NEW_PRIORITY_REQUEST: &str = "NewPriorityRequest(address,uint64,uint8,bytes,uint256)";
+const DEPOSIT_OP_TYPE: u8 = 1;
+/// op type, chain id, account id, sub account id, token id, target token id, amount, owner.
+const DEPOSIT_PUBDATA_LEN: usize = 1 + 1 + 4 + 1 + 2 + 2 + 16 + 32;
+
+pub(crate) struct Deposit {
+    pub serial_id: u64,
+    pub chain_id: u8,
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub token_id: u16,
+    pub target_token_id: u16,
+    pub amount: u128,
+    /// The zkLink address credited on layer2.
+    pub owner: H256,
+}
+
+/// Decode the deposit carried by a `NewPriorityRequest` log of the zkLink contract,
+/// None for any other log.
+pub(crate) fn decode_deposit(log: &Log) -> Result<Option<Deposit>> {
+    let topic = H256::from_slice(&Keccak256::digest(NEW_PRIORITY_REQUEST));
+    if log.topics.first() != Some(&topic) {
+        return Ok(None);
+    }
+    let params = [
+        ParamType::Address,
+        ParamType::Uint(64),
+        ParamType::Uint(8),
+        ParamType::Bytes,
+        ParamType::Uint(256),
+    ];
+    let tokens = decode(&params, &log.data)?;
+    let (Some(serial_id), Some(op_type), Some(pubdata)) = (
+        tokens[1].clone().into_uint(),
+        tokens[2].clone().into_uint(),
+        tokens[3].clone().into_bytes(),
+    ) else {
+        return Ok(None);
+    };
+    if op_type.as_u32() != DEPOSIT_OP_TYPE as u32 {
+        return Ok(None);
+    }
+    ensure!(
+        pubdata.len() >= DEPOSIT_PUBDATA_LEN,
+        "deposit pubdata is {} bytes, expected {}",
+        pubdata.len(),
+        DEPOSIT_PUBDATA_LEN
+    );
+    Ok(Some(Deposit {
+        serial_id: serial_id.as_u64(),
+        chain_id: pubdata[1],
+        account_id: u32::from_be_bytes(pubdata[2..6].try_into()?),
+        sub_account_id: pubdata[6],
+        token_id: u16::from_be_bytes(pubdata[7..9].try_into()?),
+        target_token_id: u16::from_be_bytes(pubdata[9..11].try_into()?),
+        amount: u128::from_be_bytes(pubdata[11..27].try_into()?),
+        owner: H256::from_slice(&pubdata[27..59]),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+    use ethers::types::{Address, U256};
+
+    fn pubdata(op_type: u8, len: usize) -> Vec<u8> {
+        let mut pubdata = vec![op_type, 2];
+        pubdata.extend(7u32.to_be_bytes());
+        pubdata.push(1);
+        pubdata.extend(18u16.to_be_bytes());
+        pubdata.extend(17u16.to_be_bytes());
+        pubdata.extend(1_000_000u128.to_be_bytes());
+        pubdata.extend([0x11u8; 32]);
+        pubdata.resize(len, 0);
+        pubdata
+    }
+
+    fn log(topic: &str, op_type: u8, pubdata: Vec<u8>) -> Log {
+        Log {
+            topics: vec![H256::from_slice(&Keccak256::digest(topic))],
+            data: encode(&[
+                Token::Address(Address::repeat_byte(0x22)),
+                Token::Uint(U256::from(42)),
+                Token::Uint(op_type.into()),
+                Token::Bytes(pubdata),
+                Token::Uint(U256::from(1_000)),
+            ])
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deposit() {
+        let deposit = decode_deposit(&log(NEW_PRIORITY_REQUEST, 1, pubdata(1, 59)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(deposit.serial_id, 42);
+        assert_eq!(deposit.chain_id, 2);
+        assert_eq!(deposit.account_id, 7);
+        assert_eq!(deposit.sub_account_id, 1);
+        assert_eq!(deposit.token_id, 18);
+        assert_eq!(deposit.target_token_id, 17);
+        assert_eq!(deposit.amount, 1_000_000);
+        assert_eq!(deposit.owner, H256::repeat_byte(0x11));
+    }
+
+    #[test]
+    fn padded_deposit_pubdata() {
+        // bytes after the deposit layout are ignored
+        let deposit = decode_deposit(&log(NEW_PRIORITY_REQUEST, 1, pubdata(1, 64)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(deposit.owner, H256::repeat_byte(0x11));
+    }
+
+    #[test]
+    fn other_logs_are_skipped() {
+        // a full exit request
+        assert!(
+            decode_deposit(&log(NEW_PRIORITY_REQUEST, 5, pubdata(5, 59)))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            decode_deposit(&log("Transfer(address,address,uint256)", 1, pubdata(1, 59)))
+                .unwrap()
+                .is_none()
+        );
+        assert!(decode_deposit(&Log::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn short_deposit_pubdata() {
+        assert!(decode_deposit(&log(NEW_PRIORITY_REQUEST, 1, pubdata(1, 58))).is_err());
+    }
+}
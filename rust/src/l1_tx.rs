@@ -0,0 +1,363 @@
+use anyhow::{ensure, Result};
+use ethers::abi::{AbiParser, Token};
+use ethers::signers::LocalWallet;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{
+    Address, Bytes, Eip1559TransactionRequest, Signature, TransactionRequest, H256, U256,
+};
+use ethers::utils::rlp::Rlp;
+use sha3::{Digest, Keccak256};
+
+/// Everything needed to build a layer1 tx without asking the node.
+pub(crate) struct L1TxParams {
+    pub chain_id: u64,
+    pub to: Address,
+    pub value: U256,
+    pub nonce: U256,
+    /// None to leave it unset, e.g. to estimate the gas of the tx first.
+    pub gas: Option<U256>,
+    pub gas_price: U256,
+    /// The EIP-1559 priority fee, `default_priority_fee` if None.
+    pub max_priority_fee: Option<U256>,
+    pub is_eip1559: bool,
+}
+
+/// 1.5 gwei, the tip most wallets default to, capped by the max fee.
+fn default_priority_fee(max_fee: U256) -> U256 {
+    max_fee.min(U256::from(1_500_000_000u64))
+}
+
+/// ABI encode a call from a signature such as `requestFullExit(uint32,uint8,uint16,bool)`.
+fn encode_call(signature: &str, tokens: &[Token]) -> Result<Vec<u8>> {
+    let function = AbiParser::default().parse_function(&format!("function {signature}"))?;
+    Ok(function.encode_input(tokens)?)
+}
+
+/// Left pad a 20 bytes layer1 address to the `bytes32` zkLink address taken by the deposit
+/// functions, 32 bytes addresses of non EVM chains are kept as is.
+fn zklink_address(address: &[u8]) -> Result<Token> {
+    ensure!(
+        address.len() == 20 || address.len() == 32,
+        "zkLink address must be 20 or 32 bytes, got {}",
+        address.len()
+    );
+    let mut bytes = [0u8; 32];
+    bytes[32 - address.len()..].copy_from_slice(address);
+    Ok(Token::FixedBytes(bytes.to_vec()))
+}
+
+pub(crate) fn approve_call(spender: Address, amount: U256) -> Result<Vec<u8>> {
+    encode_call(
+        "approve(address,uint256)",
+        &[Token::Address(spender), Token::Uint(amount)],
+    )
+}
+
+pub(crate) fn deposit_erc20_call(
+    token: Address,
+    amount: U256,
+    deposit_to: &[u8],
+    sub_account_id: u8,
+    mapping: bool,
+) -> Result<Vec<u8>> {
+    encode_call(
+        "depositERC20(address,uint104,bytes32,uint8,bool)",
+        &[
+            Token::Address(token),
+            Token::Uint(amount),
+            zklink_address(deposit_to)?,
+            Token::Uint(sub_account_id.into()),
+            Token::Bool(mapping),
+        ],
+    )
+}
+
+pub(crate) fn deposit_eth_call(deposit_to: &[u8], sub_account_id: u8) -> Result<Vec<u8>> {
+    encode_call(
+        "depositETH(bytes32,uint8)",
+        &[
+            zklink_address(deposit_to)?,
+            Token::Uint(sub_account_id.into()),
+        ],
+    )
+}
+
+pub(crate) fn set_auth_pubkey_hash_call(pubkey_hash: Vec<u8>, nonce: u32) -> Result<Vec<u8>> {
+    ensure!(pubkey_hash.len() == 20, "pubkey hash must be 20 bytes");
+    encode_call(
+        "setAuthPubkeyHash(bytes,uint32)",
+        &[Token::Bytes(pubkey_hash), Token::Uint(nonce.into())],
+    )
+}
+
+pub(crate) fn full_exit_call(
+    account_id: u32,
+    sub_account_id: u8,
+    token_id: u16,
+    mapping: bool,
+) -> Result<Vec<u8>> {
+    encode_call(
+        "requestFullExit(uint32,uint8,uint16,bool)",
+        &[
+            Token::Uint(account_id.into()),
+            Token::Uint(sub_account_id.into()),
+            Token::Uint(token_id.into()),
+            Token::Bool(mapping),
+        ],
+    )
+}
+
+/// An EIP-1559 tx uses `gas_price` as the max fee.
+pub(crate) fn build_tx(params: L1TxParams, data: Vec<u8>) -> TypedTransaction {
+    let mut tx: TypedTransaction = if params.is_eip1559 {
+        let priority_fee = params
+            .max_priority_fee
+            .unwrap_or_else(|| default_priority_fee(params.gas_price));
+        Eip1559TransactionRequest::new()
+            .chain_id(params.chain_id)
+            .to(params.to)
+            .value(params.value)
+            .nonce(params.nonce)
+            .max_fee_per_gas(params.gas_price)
+            .max_priority_fee_per_gas(priority_fee)
+            .data(data)
+            .into()
+    } else {
+        TransactionRequest::new()
+            .chain_id(params.chain_id)
+            .to(params.to)
+            .value(params.value)
+            .nonce(params.nonce)
+            .gas_price(params.gas_price)
+            .data(data)
+            .into()
+    };
+    if let Some(gas) = params.gas {
+        tx.set_gas(gas);
+    }
+    tx
+}
+
+/// The raw signed tx, ready for `eth_sendRawTransaction`, and its hash.
+pub(crate) fn encode_signed(tx: &TypedTransaction, signature: &Signature) -> (Bytes, H256) {
+    let raw = tx.rlp_signed(signature);
+    let hash = H256::from_slice(&Keccak256::digest(&raw));
+    (raw, hash)
+}
+
+pub(crate) fn sign_tx(wallet: &LocalWallet, tx: &TypedTransaction) -> Result<(Bytes, H256)> {
+    let signature = wallet.sign_transaction_sync(tx)?;
+    Ok(encode_signed(tx, &signature))
+}
+
+/// Decode a raw signed tx and recover the account that signed it.
+pub(crate) fn decode_signed(raw: &[u8]) -> Result<(TypedTransaction, Address)> {
+    let (tx, signature) = TypedTransaction::decode_signed(&Rlp::new(raw))?;
+    let signer = signature.recover(tx.sighash())?;
+    Ok((tx, signer))
+}
+
+fn priority_fee(tx: &TypedTransaction) -> Option<U256> {
+    match tx {
+        TypedTransaction::Eip1559(tx) => tx.max_priority_fee_per_gas,
+        _ => None,
+    }
+}
+
+/// Check that a tx signed outside the SDK is the one that was handed to the signer.
+pub(crate) fn ensure_same_tx(expected: &TypedTransaction, signed: &TypedTransaction) -> Result<()> {
+    let fields = [
+        (
+            "type",
+            std::mem::discriminant(expected) == std::mem::discriminant(signed),
+        ),
+        ("chain id", expected.chain_id() == signed.chain_id()),
+        ("recipient", expected.to() == signed.to()),
+        ("nonce", expected.nonce() == signed.nonce()),
+        (
+            "value",
+            expected.value().copied().unwrap_or_default()
+                == signed.value().copied().unwrap_or_default(),
+        ),
+        (
+            "data",
+            expected.data().cloned().unwrap_or_default()
+                == signed.data().cloned().unwrap_or_default(),
+        ),
+        ("gas limit", expected.gas() == signed.gas()),
+        ("gas price", expected.gas_price() == signed.gas_price()),
+        (
+            "priority fee",
+            priority_fee(expected) == priority_fee(signed),
+        ),
+    ];
+    for (field, same) in fields {
+        ensure!(same, "the external signer changed the {field} of the tx");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::Signer;
+    use std::str::FromStr;
+
+    fn params(is_eip1559: bool, max_priority_fee: Option<U256>) -> L1TxParams {
+        L1TxParams {
+            chain_id: 5,
+            to: Address::repeat_byte(0x33),
+            value: U256::from(1_000_000_000_000_000u64),
+            nonce: U256::from(7),
+            gas: Some(U256::from(21_000)),
+            gas_price: U256::from(30_000_000_000u64),
+            max_priority_fee,
+            is_eip1559,
+        }
+    }
+
+    fn wallet() -> LocalWallet {
+        LocalWallet::from_str("be725250b123a39dab5b7579334d5888987c72a58f4508062545fe6e08ca94f4")
+            .unwrap()
+    }
+
+    #[test]
+    fn selectors() {
+        let address = [0x11u8; 20];
+        let selector = |data: Vec<u8>| format!("0x{}", hex::encode(&data[..4]));
+        assert_eq!(
+            selector(approve_call(Address::zero(), U256::zero()).unwrap()),
+            "0x095ea7b3"
+        );
+        assert_eq!(
+            selector(deposit_erc20_call(Address::zero(), U256::one(), &address, 0, false).unwrap()),
+            "0x862a85b2"
+        );
+        assert_eq!(
+            selector(deposit_eth_call(&address, 0).unwrap()),
+            "0x7648ce45"
+        );
+        assert_eq!(
+            selector(set_auth_pubkey_hash_call(vec![0u8; 20], 0).unwrap()),
+            "0x595a5ebc"
+        );
+        assert_eq!(
+            selector(full_exit_call(1, 0, 17, false).unwrap()),
+            "0x57cf2a19"
+        );
+    }
+
+    #[test]
+    fn deposit_to_is_left_padded() {
+        let address = [0x11u8; 20];
+        let data = deposit_eth_call(&address, 1).unwrap();
+        assert_eq!(&data[4..16], &[0u8; 12]);
+        assert_eq!(&data[16..36], &address);
+        assert_eq!(data[67], 1);
+
+        let data = deposit_erc20_call(Address::repeat_byte(0x22), U256::from(5), &address, 2, true)
+            .unwrap();
+        assert_eq!(&data[16..36], &[0x22u8; 20]);
+        assert_eq!(data[67], 5);
+        assert_eq!(&data[68..80], &[0u8; 12]);
+        assert_eq!(&data[80..100], &address);
+        assert_eq!(data[131], 2);
+        assert_eq!(data[163], 1);
+    }
+
+    #[test]
+    fn non_evm_deposit_to_is_kept() {
+        let address = [0x22u8; 32];
+        let data = deposit_eth_call(&address, 0).unwrap();
+        assert_eq!(&data[4..36], &address);
+        assert!(deposit_eth_call(&[0u8; 10], 0).is_err());
+        assert!(deposit_eth_call(&[0u8; 33], 0).is_err());
+        assert!(set_auth_pubkey_hash_call(vec![0u8; 32], 0).is_err());
+    }
+
+    #[test]
+    fn priority_fee() {
+        let TypedTransaction::Eip1559(tx) = build_tx(params(true, None), vec![]) else {
+            panic!("not an EIP-1559 tx")
+        };
+        assert_eq!(
+            tx.max_priority_fee_per_gas,
+            Some(U256::from(1_500_000_000u64))
+        );
+        assert_eq!(tx.max_fee_per_gas, Some(U256::from(30_000_000_000u64)));
+
+        let mut low_fee = params(true, None);
+        low_fee.gas_price = U256::from(1_000);
+        let TypedTransaction::Eip1559(tx) = build_tx(low_fee, vec![]) else {
+            panic!("not an EIP-1559 tx")
+        };
+        assert_eq!(tx.max_priority_fee_per_gas, Some(U256::from(1_000)));
+
+        let TypedTransaction::Eip1559(tx) = build_tx(params(true, Some(U256::from(7))), vec![])
+        else {
+            panic!("not an EIP-1559 tx")
+        };
+        assert_eq!(tx.max_priority_fee_per_gas, Some(U256::from(7)));
+    }
+
+    fn sign_and_decode(is_eip1559: bool) {
+        let wallet = wallet().with_chain_id(5u64);
+        let data = deposit_eth_call(&[0x11u8; 20], 0).unwrap();
+        let tx = build_tx(params(is_eip1559, None), data.clone());
+        let (raw, hash) = sign_tx(&wallet, &tx).unwrap();
+        assert_eq!(hash, H256::from_slice(&Keccak256::digest(&raw)));
+
+        let (decoded, signer) = decode_signed(&raw).unwrap();
+        assert_eq!(signer, wallet.address());
+        assert_eq!(matches!(decoded, TypedTransaction::Eip1559(_)), is_eip1559);
+        assert_eq!(decoded.chain_id(), Some(5u64.into()));
+        assert_eq!(decoded.to_addr(), Some(&Address::repeat_byte(0x33)));
+        assert_eq!(decoded.nonce(), Some(&U256::from(7)));
+        assert_eq!(decoded.gas(), Some(&U256::from(21_000)));
+        assert_eq!(decoded.data(), Some(&Bytes::from(data)));
+    }
+
+    #[test]
+    fn changes_of_the_external_signer_are_detected() {
+        let wallet = wallet().with_chain_id(5u64);
+        for is_eip1559 in [false, true] {
+            let tx = build_tx(params(is_eip1559, None), vec![1, 2, 3]);
+            let (raw, _) = sign_tx(&wallet, &tx).unwrap();
+            let (signed, _) = decode_signed(&raw).unwrap();
+            ensure_same_tx(&tx, &signed).unwrap();
+
+            let changes: [fn(&mut L1TxParams); 6] = [
+                |params| params.chain_id = 1,
+                |params| params.nonce = U256::from(8),
+                |params| params.gas = Some(U256::from(50_000)),
+                |params| params.gas_price = U256::from(40_000_000_000u64),
+                |params| params.value = U256::zero(),
+                |params| params.is_eip1559 = !params.is_eip1559,
+            ];
+            for change in changes {
+                let mut changed = params(is_eip1559, None);
+                change(&mut changed);
+                let wallet = wallet.clone().with_chain_id(changed.chain_id);
+                let (raw, _) = sign_tx(&wallet, &build_tx(changed, vec![1, 2, 3])).unwrap();
+                let (signed, _) = decode_signed(&raw).unwrap();
+                assert!(ensure_same_tx(&tx, &signed).is_err());
+            }
+        }
+
+        let tx = build_tx(params(true, None), vec![]);
+        let higher_tip = build_tx(params(true, Some(U256::from(2_000_000_000u64))), vec![]);
+        let (raw, _) = sign_tx(&wallet, &higher_tip).unwrap();
+        let (signed, _) = decode_signed(&raw).unwrap();
+        assert!(ensure_same_tx(&tx, &signed).is_err());
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        sign_and_decode(false);
+    }
+
+    #[test]
+    fn eip1559_round_trip() {
+        sign_and_decode(true);
+    }
+}
@@ -1,2 +1,14 @@
 pub mod api;
+mod air_gap;
+mod clock;
 mod frb_generated; /* AUTO INJECTED BY flutter_rust_bridge. This line may not be accurate, and you can change it according to your needs. */
+mod funding;
+mod global_var;
+mod l1_event;
+mod l1_tx;
+mod liquidation;
+mod margin;
+mod matching;
+mod order_book;
+mod scale;
+mod slots;
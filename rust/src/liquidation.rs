@@ -0,0 +1,203 @@
+use crate::margin::{
+    evaluate, AccountRisk, CollateralSnapshot, MarketSnapshot, PositionSnapshot,
+    MARGIN_RATE_PRECISION,
+};
+use anyhow::{ensure, Result};
+use num::{BigInt, BigUint, Signed, Zero};
+
+pub(crate) struct AccountSnapshot {
+    pub account_id: u32,
+    pub sub_account_id: u8,
+    pub positions: Vec<PositionSnapshot>,
+    pub collaterals: Vec<CollateralSnapshot>,
+}
+
+/// Accounts whose equity is below their maintenance margin, the lowest margin ratio first.
+pub(crate) fn liquidatable<'a>(
+    accounts: &'a [AccountSnapshot],
+    market: &MarketSnapshot,
+) -> Result<Vec<(&'a AccountSnapshot, AccountRisk)>> {
+    let mut candidates = Vec::new();
+    for account in accounts {
+        let risk = evaluate(&account.positions, &account.collaterals, market)?;
+        if risk.is_liquidatable() {
+            candidates.push((account, risk));
+        }
+    }
+    candidates.sort_by_key(|(_, a)| a.margin_ratio());
+    Ok(candidates)
+}
+
+pub(crate) struct AdlRank<'a> {
+    pub account: &'a AccountSnapshot,
+    pub position: &'a PositionSnapshot,
+    pub unrealized_pnl: BigInt,
+    /// Notional over equity, in `MARGIN_RATE_PRECISION`.
+    pub leverage: BigInt,
+    /// Pnl ratio times leverage, in `MARGIN_RATE_PRECISION`.
+    pub score: BigInt,
+}
+
+/// Rank the profitable `is_long` positions of `pair_id` for auto deleveraging, the most
+/// profitable and leveraged first. Accounts without positive equity are left out.
+pub(crate) fn rank_adl<'a>(
+    accounts: &'a [AccountSnapshot],
+    pair_id: u16,
+    is_long: bool,
+    market: &MarketSnapshot,
+) -> Result<Vec<AdlRank<'a>>> {
+    let precision = BigInt::from(MARGIN_RATE_PRECISION);
+    let mut ranks = Vec::new();
+    for account in accounts {
+        let Some((index, position)) = account
+            .positions
+            .iter()
+            .enumerate()
+            .find(|(_, p)| p.pair_id == pair_id && p.is_long == is_long && !p.size.is_zero())
+        else {
+            continue;
+        };
+        let risk = evaluate(&account.positions, &account.collaterals, market)?;
+        let position_risk = &risk.positions[index];
+        if !risk.equity.is_positive()
+            || !position_risk.unrealized_pnl.is_positive()
+            || !position.value.is_positive()
+        {
+            continue;
+        }
+        let pnl_ratio = &position_risk.unrealized_pnl * &precision / &position.value;
+        let leverage = &position_risk.notional * &precision / &risk.equity;
+        ranks.push(AdlRank {
+            account,
+            position,
+            unrealized_pnl: position_risk.unrealized_pnl.clone(),
+            score: &pnl_ratio * &leverage / &precision,
+            leverage,
+        });
+    }
+    ranks.sort_by(|a, b| b.score.cmp(&a.score));
+    Ok(ranks)
+}
+
+/// Split `adl_size` over the counterparty sizes in rank order, each taking as much as it
+/// holds. Fails if the counterparties don't cover the whole size.
+pub(crate) fn split_adl(sizes: &[BigUint], adl_size: &BigUint) -> Result<Vec<BigUint>> {
+    let mut remaining = adl_size.clone();
+    let mut split = Vec::new();
+    for size in sizes {
+        if remaining.is_zero() {
+            break;
+        }
+        let size = (&remaining).min(size).clone();
+        remaining -= &size;
+        split.push(size);
+    }
+    ensure!(
+        remaining.is_zero(),
+        "candidates only cover part of the adl size, {} left",
+        remaining
+    );
+    Ok(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::margin::{price_precision, ContractRisk};
+
+    fn big(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn account(account_id: u32, is_long: bool, value: i64, collateral: i64) -> AccountSnapshot {
+        AccountSnapshot {
+            account_id,
+            sub_account_id: 0,
+            positions: vec![PositionSnapshot {
+                pair_id: 1,
+                is_long,
+                size: big(10),
+                value: big(value),
+            }],
+            collaterals: vec![CollateralSnapshot {
+                token_id: 1,
+                balance: big(collateral),
+            }],
+        }
+    }
+
+    // 10% initial and 5% maintenance margin, so 50 at a price of 100
+    fn market(price: i64) -> MarketSnapshot {
+        let mut market = MarketSnapshot::default();
+        market.contract_infos.insert(
+            1,
+            ContractRisk {
+                initial_margin_rate: 100,
+                maintenance_margin_rate: 50,
+            },
+        );
+        market.collateral_ratios.insert(1, 100);
+        market.spot_prices.insert(1, price_precision());
+        market
+            .contract_prices
+            .insert(1, big(price) * price_precision());
+        market
+    }
+
+    #[test]
+    fn liquidatable_lowest_margin_ratio_first() {
+        let accounts = [
+            account(1, true, 1000, 40),
+            account(2, true, 1000, 20),
+            account(3, true, 1000, 100),
+            account(4, true, 1000, 50),
+            account(5, true, 1000, 49),
+        ];
+        let candidates = liquidatable(&accounts, &market(100)).unwrap();
+        let ranked: Vec<_> = candidates
+            .iter()
+            .map(|(account, risk)| (account.account_id, risk.margin_ratio().unwrap()))
+            .collect();
+        // account 4 sits exactly at its maintenance margin and is not liquidatable yet
+        assert_eq!(ranked, vec![(2, big(400)), (1, big(800)), (5, big(980))]);
+    }
+
+    #[test]
+    fn no_position_is_never_liquidatable() {
+        let mut empty = account(1, true, 1000, -10);
+        empty.positions.clear();
+        assert!(liquidatable(&[empty], &market(100)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn adl_ranks_profitable_positions_by_score() {
+        let accounts = [
+            account(1, true, 1000, 100),
+            account(2, true, 500, 0),
+            account(3, true, 1200, 1000),
+            account(4, false, 500, 1000),
+        ];
+        let ranks = rank_adl(&accounts, 1, true, &market(110)).unwrap();
+        let ranked: Vec<_> = ranks
+            .iter()
+            .map(|rank| (rank.account.account_id, rank.score.clone()))
+            .collect();
+        // account 1: pnl ratio 100, leverage 1100 / 200. account 2: pnl ratio 1200, leverage 1100 / 600
+        assert_eq!(ranked, vec![(2, big(2199)), (1, big(550))]);
+        assert_eq!(ranks[0].unrealized_pnl, big(600));
+        assert_eq!(ranks[0].leverage, big(1833));
+    }
+
+    #[test]
+    fn adl_size_is_split_in_rank_order() {
+        let sizes: Vec<BigUint> = [5u32, 10, 3].into_iter().map(BigUint::from).collect();
+        let split = |size: u32| split_adl(&sizes, &BigUint::from(size));
+        assert_eq!(
+            split(12).unwrap(),
+            vec![BigUint::from(5u32), BigUint::from(7u32)]
+        );
+        assert_eq!(split(18).unwrap(), sizes);
+        assert!(split(0).unwrap().is_empty());
+        assert!(split(19).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::{anyhow, ensure, Result};
+use num::{BigInt, Integer, Signed, Zero};
+use std::collections::HashMap;
+
+/// Contract and spot prices are fixed point numbers with 18 decimals.
+pub(crate) const PRICE_DECIMALS: u32 = 18;
+/// `initial_margin_rate` and `maintenance_margin_rate` of `ContractInfo` are per mille.
+pub(crate) const MARGIN_RATE_PRECISION: u16 = 1000;
+/// `ratio` of `MarginInfo` is a percentage applied to the collateral value.
+pub(crate) const COLLATERAL_RATIO_PRECISION: u8 = 100;
+
+pub(crate) fn price_precision() -> BigInt {
+    BigInt::from(10u8).pow(PRICE_DECIMALS)
+}
+
+pub(crate) struct PositionSnapshot {
+    pub pair_id: u16,
+    pub is_long: bool,
+    pub size: BigInt,
+    /// The open value of the position in the settlement currency.
+    pub value: BigInt,
+}
+
+pub(crate) struct CollateralSnapshot {
+    pub token_id: u32,
+    pub balance: BigInt,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct ContractRisk {
+    pub initial_margin_rate: u16,
+    pub maintenance_margin_rate: u16,
+}
+
+#[derive(Default)]
+pub(crate) struct MarketSnapshot {
+    pub contract_infos: HashMap<u16, ContractRisk>,
+    pub collateral_ratios: HashMap<u32, u8>,
+    pub contract_prices: HashMap<u16, BigInt>,
+    pub spot_prices: HashMap<u32, BigInt>,
+}
+
+impl MarketSnapshot {
+    fn contract_price(&self, pair_id: u16) -> Result<&BigInt> {
+        self.contract_prices
+            .get(&pair_id)
+            .ok_or_else(|| anyhow!("missing contract price of pair {pair_id}"))
+    }
+
+    fn contract_info(&self, pair_id: u16) -> Result<ContractRisk> {
+        self.contract_infos
+            .get(&pair_id)
+            .copied()
+            .ok_or_else(|| anyhow!("missing contract info of pair {pair_id}"))
+    }
+}
+
+pub(crate) struct PositionRisk {
+    pub pair_id: u16,
+    pub notional: BigInt,
+    pub unrealized_pnl: BigInt,
+    pub initial_margin: BigInt,
+    pub maintenance_margin: BigInt,
+    pub liquidation_price: Option<BigInt>,
+}
+
+pub(crate) struct AccountRisk {
+    pub collateral_value: BigInt,
+    pub unrealized_pnl: BigInt,
+    pub equity: BigInt,
+    pub initial_margin: BigInt,
+    pub maintenance_margin: BigInt,
+    pub positions: Vec<PositionRisk>,
+}
+
+impl AccountRisk {
+    /// `equity / maintenance_margin` in `MARGIN_RATE_PRECISION`, `None` without open positions.
+    pub fn margin_ratio(&self) -> Option<BigInt> {
+        if self.maintenance_margin.is_zero() {
+            return None;
+        }
+        Some(&self.equity * BigInt::from(MARGIN_RATE_PRECISION) / &self.maintenance_margin)
+    }
+
+    pub fn is_liquidatable(&self) -> bool {
+        !self.maintenance_margin.is_zero() && self.equity < self.maintenance_margin
+    }
+
+    /// Equity left above the initial margin requirement, negative when no new position can be opened.
+    pub fn available_margin(&self) -> BigInt {
+        &self.equity - &self.initial_margin
+    }
+}
+
+pub(crate) fn notional(size: &BigInt, price: &BigInt) -> BigInt {
+    size * price / price_precision()
+}
+
+pub(crate) fn unrealized_pnl(position: &PositionSnapshot, price: &BigInt) -> BigInt {
+    let notional = notional(&position.size, price);
+    if position.is_long {
+        notional - &position.value
+    } else {
+        &position.value - notional
+    }
+}
+
+// margin requirements are rounded up, in favor of the protocol
+fn margin_requirement(notional: &BigInt, rate: u16) -> BigInt {
+    (notional * BigInt::from(rate)).div_ceil(&BigInt::from(MARGIN_RATE_PRECISION))
+}
+
+pub(crate) fn collateral_value(
+    collaterals: &[CollateralSnapshot],
+    market: &MarketSnapshot,
+) -> Result<BigInt> {
+    let mut total = BigInt::zero();
+    for collateral in collaterals {
+        let price = market
+            .spot_prices
+            .get(&collateral.token_id)
+            .ok_or_else(|| anyhow!("missing spot price of token {}", collateral.token_id))?;
+        let ratio = market
+            .collateral_ratios
+            .get(&collateral.token_id)
+            .ok_or_else(|| anyhow!("missing margin info of token {}", collateral.token_id))?;
+        total += &collateral.balance * price / price_precision() * BigInt::from(*ratio)
+            / BigInt::from(COLLATERAL_RATIO_PRECISION);
+    }
+    Ok(total)
+}
+
+pub(crate) fn evaluate(
+    positions: &[PositionSnapshot],
+    collaterals: &[CollateralSnapshot],
+    market: &MarketSnapshot,
+) -> Result<AccountRisk> {
+    let collateral_value = collateral_value(collaterals, market)?;
+    let mut risks = Vec::with_capacity(positions.len());
+    for position in positions {
+        ensure!(
+            !position.size.is_negative(),
+            "size of pair {} position is negative",
+            position.pair_id
+        );
+        let price = market.contract_price(position.pair_id)?;
+        let info = market.contract_info(position.pair_id)?;
+        let notional = notional(&position.size, price);
+        risks.push(PositionRisk {
+            pair_id: position.pair_id,
+            unrealized_pnl: unrealized_pnl(position, price),
+            initial_margin: margin_requirement(&notional, info.initial_margin_rate),
+            maintenance_margin: margin_requirement(&notional, info.maintenance_margin_rate),
+            notional,
+            liquidation_price: None,
+        });
+    }
+
+    let unrealized_pnl: BigInt = risks.iter().map(|r| &r.unrealized_pnl).sum();
+    let equity = &collateral_value + &unrealized_pnl;
+    let initial_margin = risks.iter().map(|r| &r.initial_margin).sum();
+    let maintenance_margin: BigInt = risks.iter().map(|r| &r.maintenance_margin).sum();
+
+    for (position, risk) in positions.iter().zip(risks.iter_mut()) {
+        let info = market.contract_info(position.pair_id)?;
+        let other_equity = &equity - &risk.unrealized_pnl;
+        let other_maintenance = &maintenance_margin - &risk.maintenance_margin;
+        risk.liquidation_price = liquidation_price(
+            position,
+            info.maintenance_margin_rate,
+            &other_equity,
+            &other_maintenance,
+        );
+    }
+
+    Ok(AccountRisk {
+        collateral_value,
+        unrealized_pnl,
+        equity,
+        initial_margin,
+        maintenance_margin,
+        positions: risks,
+    })
+}
+
+/// The price of the position's pair at which equity meets the maintenance margin,
+/// all other positions and collaterals kept at their current value.
+fn liquidation_price(
+    position: &PositionSnapshot,
+    maintenance_margin_rate: u16,
+    other_equity: &BigInt,
+    other_maintenance: &BigInt,
+) -> Option<BigInt> {
+    if position.size.is_zero() {
+        return None;
+    }
+    let rate_precision = BigInt::from(MARGIN_RATE_PRECISION);
+    let rate = BigInt::from(maintenance_margin_rate);
+    if position.is_long {
+        // other_equity + size * p - value = other_maintenance + size * p * rate
+        let numerator = (other_maintenance + &position.value - other_equity)
+            * price_precision()
+            * &rate_precision;
+        let denominator = &position.size * (&rate_precision - rate);
+        if !numerator.is_positive() || !denominator.is_positive() {
+            return None;
+        }
+        Some(numerator / denominator)
+    } else {
+        // other_equity + value - size * p = other_maintenance + size * p * rate
+        let numerator = (other_equity + &position.value - other_maintenance)
+            * price_precision()
+            * &rate_precision;
+        let denominator = &position.size * (&rate_precision + rate);
+        if !numerator.is_positive() {
+            return Some(BigInt::zero());
+        }
+        Some(numerator / denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(units: u64) -> BigInt {
+        BigInt::from(units) * price_precision()
+    }
+
+    fn big(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn position(pair_id: u16, is_long: bool, size: i64, value: i64) -> PositionSnapshot {
+        PositionSnapshot {
+            pair_id,
+            is_long,
+            size: big(size),
+            value: big(value),
+        }
+    }
+
+    fn collateral(balance: i64) -> Vec<CollateralSnapshot> {
+        vec![CollateralSnapshot {
+            token_id: 1,
+            balance: big(balance),
+        }]
+    }
+
+    // pair 1: 10% initial, 5% maintenance. pair 2: 20% initial, 10% maintenance
+    fn market(price_1: u64, price_2: u64) -> MarketSnapshot {
+        let mut market = MarketSnapshot::default();
+        market.contract_infos.insert(
+            1,
+            ContractRisk {
+                initial_margin_rate: 100,
+                maintenance_margin_rate: 50,
+            },
+        );
+        market.contract_infos.insert(
+            2,
+            ContractRisk {
+                initial_margin_rate: 200,
+                maintenance_margin_rate: 100,
+            },
+        );
+        market.collateral_ratios.insert(1, 100);
+        market.spot_prices.insert(1, price(1));
+        market.contract_prices.insert(1, price(price_1));
+        market.contract_prices.insert(2, price(price_2));
+        market
+    }
+
+    #[test]
+    fn long_liquidation_price() {
+        let risk = evaluate(
+            &[position(1, true, 10, 1000)],
+            &collateral(100),
+            &market(100, 1),
+        )
+        .unwrap();
+        assert_eq!(risk.equity, big(100));
+        assert_eq!(risk.maintenance_margin, big(50));
+        assert_eq!(risk.initial_margin, big(100));
+        // 100 + 10p - 1000 = 10p * 5%
+        assert_eq!(
+            risk.positions[0].liquidation_price,
+            Some("94736842105263157894".parse().unwrap())
+        );
+        assert!(!risk.is_liquidatable());
+    }
+
+    #[test]
+    fn short_liquidation_price() {
+        let risk = evaluate(
+            &[position(1, false, 10, 1000)],
+            &collateral(100),
+            &market(100, 1),
+        )
+        .unwrap();
+        // 100 + 1000 - 10p = 10p * 5%
+        assert_eq!(
+            risk.positions[0].liquidation_price,
+            Some("104761904761904761904".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn short_with_non_positive_numerator_is_liquidatable_at_any_price() {
+        let positions = [position(1, false, 10, 1000), position(2, true, 100, 20000)];
+        let risk = evaluate(&positions, &collateral(0), &market(100, 100)).unwrap();
+        assert_eq!(risk.equity, big(-10000));
+        assert_eq!(risk.positions[0].liquidation_price, Some(BigInt::zero()));
+        assert!(risk.is_liquidatable());
+    }
+
+    #[test]
+    fn multiple_positions() {
+        let positions = [position(1, true, 10, 1000), position(2, false, 5, 1000)];
+        let risk = evaluate(&positions, &collateral(1000), &market(110, 180)).unwrap();
+        assert_eq!(risk.collateral_value, big(1000));
+        assert_eq!(risk.unrealized_pnl, big(200));
+        assert_eq!(risk.equity, big(1200));
+        assert_eq!(risk.initial_margin, big(290));
+        assert_eq!(risk.maintenance_margin, big(145));
+        assert_eq!(risk.margin_ratio(), Some(big(8275)));
+        assert_eq!(risk.available_margin(), big(910));
+        // the other position and the collateral cover the long whatever its price
+        assert_eq!(risk.positions[0].liquidation_price, None);
+        // 1100 + 1000 - 5p = 55 + 5p * 10%
+        assert_eq!(
+            risk.positions[1].liquidation_price,
+            Some("371818181818181818181".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn margin_requirement_rounds_up() {
+        assert_eq!(margin_requirement(&big(1001), 50), big(51));
+        assert_eq!(margin_requirement(&big(1000), 50), big(50));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::margin::PRICE_DECIMALS;
+use anyhow::{ensure, Result};
+use num::{BigInt, BigUint, Zero};
+
+/// `fee_rates` of orders and contracts are in basis points.
+const FEE_RATE_PRECISION: u32 = 10000;
+
+#[derive(Clone)]
+pub(crate) struct OrderSide {
+    pub base_token_id: u32,
+    pub quote_token_id: u32,
+    pub amount: BigUint,
+    pub price: BigUint,
+    pub is_sell: bool,
+    pub maker_fee_rate: u8,
+    pub taker_fee_rate: u8,
+    pub has_subsidy: bool,
+}
+
+pub(crate) struct SpotFill {
+    pub base_amount: BigUint,
+    pub quote_amount: BigUint,
+    /// Charged in the token each side receives, the base token for the buyer and the
+    /// quote token for the seller. Negative when the maker is subsidized.
+    pub maker_fee: BigInt,
+    pub taker_fee: BigInt,
+    pub taker_remaining: BigUint,
+    pub maker_remaining: BigUint,
+}
+
+/// The quote amount of `base_amount` at `price`, rounded down like the circuit.
+pub(crate) fn quote_amount(base_amount: &BigUint, price: &BigUint) -> BigUint {
+    base_amount * price / BigUint::from(10u8).pow(PRICE_DECIMALS)
+}
+
+/// The fee of `amount` at `fee_rate`, a subsidy is paid to the account instead of charged.
+pub(crate) fn fee(amount: &BigUint, fee_rate: u8, is_subsidy: bool) -> BigInt {
+    let fee = BigInt::from(amount * BigUint::from(fee_rate) / BigUint::from(FEE_RATE_PRECISION));
+    if is_subsidy {
+        -fee
+    } else {
+        fee
+    }
+}
+
+/// Match the taker against the maker at the maker price, as much as both orders allow.
+pub(crate) fn match_spot(taker: &OrderSide, maker: &OrderSide) -> Result<SpotFill> {
+    ensure!(
+        taker.base_token_id == maker.base_token_id && taker.quote_token_id == maker.quote_token_id,
+        "taker and maker trade different pairs"
+    );
+    ensure!(
+        taker.is_sell != maker.is_sell,
+        "taker and maker are on the same side"
+    );
+    if taker.is_sell {
+        ensure!(
+            taker.price <= maker.price,
+            "taker sell price above maker price"
+        );
+    } else {
+        ensure!(
+            taker.price >= maker.price,
+            "taker buy price below maker price"
+        );
+    }
+    let base_amount = (&taker.amount).min(&maker.amount).clone();
+    ensure!(!base_amount.is_zero(), "nothing left to match");
+    let quote_amount = quote_amount(&base_amount, &maker.price);
+    let received = |side: &OrderSide| {
+        if side.is_sell {
+            &quote_amount
+        } else {
+            &base_amount
+        }
+    };
+    Ok(SpotFill {
+        maker_fee: fee(received(maker), maker.maker_fee_rate, maker.has_subsidy),
+        taker_fee: fee(received(taker), taker.taker_fee_rate, false),
+        taker_remaining: &taker.amount - &base_amount,
+        maker_remaining: &maker.amount - &base_amount,
+        base_amount,
+        quote_amount,
+    })
+}
+
+#[derive(Clone)]
+pub(crate) struct ContractSide {
+    pub pair_id: u16,
+    pub size: BigUint,
+    pub price: BigUint,
+    pub is_long: bool,
+    pub maker_fee_rate: u8,
+    pub taker_fee_rate: u8,
+    pub has_subsidy: bool,
+}
+
+pub(crate) struct ContractFill {
+    pub maker_index: usize,
+    pub size: BigUint,
+    pub quote_amount: BigUint,
+    pub maker_fee: BigInt,
+    pub taker_fee: BigInt,
+}
+
+pub(crate) struct ContractFills {
+    pub fills: Vec<ContractFill>,
+    pub taker_remaining: BigUint,
+}
+
+/// Fill the taker against the makers in the given priority order at each maker price,
+/// stopping at the first maker whose price no longer crosses or once the taker is filled.
+pub(crate) fn match_contracts(
+    taker: &ContractSide,
+    makers: &[ContractSide],
+) -> Result<ContractFills> {
+    let mut taker_remaining = taker.size.clone();
+    let mut fills = Vec::new();
+    for (maker_index, maker) in makers.iter().enumerate() {
+        if taker_remaining.is_zero() {
+            break;
+        }
+        ensure!(
+            maker.pair_id == taker.pair_id,
+            "maker {} trades pair {} instead of {}",
+            maker_index,
+            maker.pair_id,
+            taker.pair_id
+        );
+        ensure!(
+            maker.is_long != taker.is_long,
+            "maker {} is on the taker side",
+            maker_index
+        );
+        let crosses = if taker.is_long {
+            taker.price >= maker.price
+        } else {
+            taker.price <= maker.price
+        };
+        if !crosses {
+            break;
+        }
+        let size = (&taker_remaining).min(&maker.size).clone();
+        if size.is_zero() {
+            continue;
+        }
+        let quote_amount = quote_amount(&size, &maker.price);
+        taker_remaining -= &size;
+        fills.push(ContractFill {
+            maker_index,
+            maker_fee: fee(&quote_amount, maker.maker_fee_rate, maker.has_subsidy),
+            taker_fee: fee(&quote_amount, taker.taker_fee_rate, false),
+            size,
+            quote_amount,
+        });
+    }
+    ensure!(!fills.is_empty(), "no maker crosses the taker price");
+    Ok(ContractFills {
+        fills,
+        taker_remaining,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn side(is_sell: bool, amount: u64, price: u64, has_subsidy: bool) -> OrderSide {
+        OrderSide {
+            base_token_id: 18,
+            quote_token_id: 17,
+            amount: BigUint::from(amount),
+            price: BigUint::from(price) * BigUint::from(10u8).pow(PRICE_DECIMALS),
+            is_sell,
+            maker_fee_rate: 5,
+            taker_fee_rate: 10,
+            has_subsidy,
+        }
+    }
+
+    fn big(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    #[test]
+    fn buy_taker_pays_in_base_and_seller_maker_in_quote() {
+        let fill = match_spot(
+            &side(false, 100_000, 3, false),
+            &side(true, 40_000, 2, false),
+        )
+        .unwrap();
+        assert_eq!(fill.base_amount, BigUint::from(40_000u32));
+        assert_eq!(fill.quote_amount, BigUint::from(80_000u32));
+        assert_eq!(fill.taker_fee, big(40));
+        assert_eq!(fill.maker_fee, big(40));
+        assert_eq!(fill.taker_remaining, BigUint::from(60_000u32));
+        assert!(fill.maker_remaining.is_zero());
+    }
+
+    #[test]
+    fn sell_taker_pays_in_quote_and_buyer_maker_in_base() {
+        let fill = match_spot(
+            &side(true, 40_000, 2, false),
+            &side(false, 100_000, 3, false),
+        )
+        .unwrap();
+        assert_eq!(fill.quote_amount, BigUint::from(120_000u32));
+        assert_eq!(fill.taker_fee, big(120));
+        assert_eq!(fill.maker_fee, big(20));
+        assert_eq!(fill.maker_remaining, BigUint::from(60_000u32));
+    }
+
+    #[test]
+    fn subsidized_maker_is_paid_the_fee() {
+        let buy = match_spot(
+            &side(false, 100_000, 3, false),
+            &side(true, 40_000, 2, true),
+        )
+        .unwrap();
+        assert_eq!(buy.taker_fee, big(40));
+        assert_eq!(buy.maker_fee, big(-40));
+        let sell = match_spot(
+            &side(true, 40_000, 2, false),
+            &side(false, 100_000, 3, true),
+        )
+        .unwrap();
+        assert_eq!(sell.taker_fee, big(120));
+        assert_eq!(sell.maker_fee, big(-20));
+    }
+
+    #[test]
+    fn fee_rounds_down() {
+        assert_eq!(fee(&BigUint::from(19_999u32), 5, false), big(9));
+        assert_eq!(fee(&BigUint::from(19_999u32), 5, true), big(-9));
+    }
+
+    #[test]
+    fn rejects_prices_that_do_not_cross() {
+        assert!(match_spot(&side(false, 1, 2, false), &side(true, 1, 3, false)).is_err());
+        assert!(match_spot(&side(true, 1, 3, false), &side(false, 1, 2, false)).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use num::BigUint;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+pub(crate) struct Entry<T> {
+    pub price: BigUint,
+    pub is_buy: bool,
+    pub remaining: BigUint,
+    pub item: T,
+}
+
+/// A price-time priority book, each price level keeps its entries in arrival order.
+/// There is no self-trade prevention, entries of the same account cross like any other.
+pub(crate) struct Book<T> {
+    bids: BTreeMap<BigUint, VecDeque<u32>>,
+    asks: BTreeMap<BigUint, VecDeque<u32>>,
+    entries: HashMap<u32, Entry<T>>,
+    next_id: u32,
+}
+
+impl<T> Default for Book<T> {
+    fn default() -> Self {
+        Self {
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            entries: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> Book<T> {
+    fn levels(&mut self, is_buy: bool) -> &mut BTreeMap<BigUint, VecDeque<u32>> {
+        if is_buy {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        }
+    }
+
+    pub fn insert(&mut self, price: BigUint, is_buy: bool, remaining: BigUint, item: T) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.levels(is_buy)
+            .entry(price.clone())
+            .or_default()
+            .push_back(id);
+        self.entries.insert(
+            id,
+            Entry {
+                price,
+                is_buy,
+                remaining,
+                item,
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Entry<T>> {
+        self.entries.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn cancel(&mut self, id: u32) -> Option<T> {
+        let entry = self.entries.remove(&id)?;
+        let levels = self.levels(entry.is_buy);
+        if let Some(level) = levels.get_mut(&entry.price) {
+            level.retain(|e| *e != id);
+            if level.is_empty() {
+                levels.remove(&entry.price);
+            }
+        }
+        Some(entry.item)
+    }
+
+    /// Reduce the remaining amount of an entry, it leaves the book once fully filled.
+    pub fn fill(&mut self, id: u32, amount: &BigUint) {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return;
+        };
+        if &entry.remaining > amount {
+            entry.remaining -= amount;
+        } else {
+            self.cancel(id);
+        }
+    }
+
+    /// The resting entries a taker on `is_buy` side at `price` crosses, best price first then oldest.
+    pub fn crossing(&self, is_buy: bool, price: &BigUint) -> Vec<u32> {
+        if is_buy {
+            self.asks
+                .range(..=price.clone())
+                .flat_map(|(_, level)| level.iter().copied())
+                .collect()
+        } else {
+            self.bids
+                .range(price.clone()..)
+                .rev()
+                .flat_map(|(_, level)| level.iter().copied())
+                .collect()
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<&BigUint> {
+        self.bids.keys().next_back()
+    }
+
+    pub fn best_ask(&self) -> Option<&BigUint> {
+        self.asks.keys().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(value: u32) -> BigUint {
+        BigUint::from(value)
+    }
+
+    #[test]
+    fn best_price_first_then_oldest() {
+        let mut book = Book::default();
+        let a = book.insert(big(101), false, big(5), "a");
+        let b = book.insert(big(100), false, big(5), "b");
+        let c = book.insert(big(100), false, big(5), "c");
+        let d = book.insert(big(102), false, big(5), "d");
+        assert_eq!(book.best_ask(), Some(&big(100)));
+        assert_eq!(book.crossing(true, &big(101)), vec![b, c, a]);
+        assert_eq!(book.crossing(true, &big(102)), vec![b, c, a, d]);
+        assert!(book.crossing(true, &big(99)).is_empty());
+    }
+
+    #[test]
+    fn bids_cross_from_the_highest() {
+        let mut book = Book::default();
+        let a = book.insert(big(99), true, big(5), "a");
+        let b = book.insert(big(100), true, big(5), "b");
+        let c = book.insert(big(99), true, big(5), "c");
+        assert_eq!(book.best_bid(), Some(&big(100)));
+        assert_eq!(book.crossing(false, &big(99)), vec![b, a, c]);
+        assert_eq!(book.crossing(false, &big(100)), vec![b]);
+        assert!(book.crossing(false, &big(101)).is_empty());
+    }
+
+    // matching is up to the caller, which crosses the taker against the book before resting it
+    #[test]
+    fn crossing_entry_is_rested_as_is() {
+        let mut book = Book::default();
+        let ask = book.insert(big(100), false, big(5), "ask");
+        assert_eq!(book.crossing(true, &big(101)), vec![ask]);
+        let bid = book.insert(big(101), true, big(5), "bid");
+        assert_eq!(book.best_bid(), Some(&big(101)));
+        assert_eq!(book.best_ask(), Some(&big(100)));
+        assert_eq!(book.crossing(false, &big(100)), vec![bid]);
+    }
+
+    #[test]
+    fn partial_fill_keeps_the_entry_and_its_priority() {
+        let mut book = Book::default();
+        let a = book.insert(big(100), false, big(5), "a");
+        let b = book.insert(big(100), false, big(5), "b");
+        book.fill(a, &big(3));
+        assert_eq!(book.get(a).unwrap().remaining, big(2));
+        assert_eq!(book.crossing(true, &big(100)), vec![a, b]);
+        book.fill(a, &big(2));
+        assert!(book.get(a).is_none());
+        assert_eq!(book.crossing(true, &big(100)), vec![b]);
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn cancel_partially_filled() {
+        let mut book = Book::default();
+        let a = book.insert(big(100), true, big(5), "a");
+        book.fill(a, &big(1));
+        assert_eq!(book.cancel(a), Some("a"));
+        assert_eq!(book.cancel(a), None);
+        assert_eq!(book.best_bid(), None);
+        assert_eq!(book.len(), 0);
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{ensure, Result};
+use num::{BigUint, Zero};
+use std::str::FromStr;
+
+fn pow10(exp: u32) -> BigUint {
+    BigUint::from(10u8).pow(exp)
+}
+
+/// Parse a human readable decimal and shift it left by `decimals`, failing if precision is lost.
+pub(crate) fn parse_decimal(value: &str, decimals: i32) -> Result<BigUint> {
+    let value = value.trim();
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    ensure!(
+        !(int.is_empty() && frac.is_empty())
+            && int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()),
+        "invalid decimal {value}"
+    );
+    let digits = format!("{int}{frac}");
+    let mantissa = BigUint::from_str(if digits.is_empty() { "0" } else { &digits })?;
+    let shift = decimals - frac.len() as i32;
+    if shift >= 0 {
+        Ok(mantissa * pow10(shift as u32))
+    } else {
+        let divisor = pow10(shift.unsigned_abs());
+        ensure!(
+            (&mantissa % &divisor).is_zero(),
+            "{value} has more decimals than supported"
+        );
+        Ok(mantissa / divisor)
+    }
+}
+
+/// The inverse of `parse_decimal`, trailing zeros of the fraction are dropped.
+pub(crate) fn format_decimal(value: &BigUint, decimals: i32) -> String {
+    if decimals <= 0 {
+        return (value * pow10(decimals.unsigned_abs())).to_string();
+    }
+    let decimals = decimals as usize;
+    let digits = format!("{value:0>width$}", width = decimals + 1);
+    let (int, frac) = digits.split_at(digits.len() - decimals);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        int.to_string()
+    } else {
+        format!("{int}.{frac}")
+    }
+}
+
+pub(crate) fn check_step(value: &BigUint, step: &BigUint, name: &str) -> Result<()> {
+    if step.is_zero() {
+        return Ok(());
+    }
+    ensure!(
+        (value % step).is_zero(),
+        "{name} {value} is not a multiple of {step}"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(value: u64) -> BigUint {
+        BigUint::from(value)
+    }
+
+    #[test]
+    fn parse_and_format() {
+        assert_eq!(parse_decimal("1.25", 4).unwrap(), big(12500));
+        assert_eq!(parse_decimal(" 3 ", 2).unwrap(), big(300));
+        assert_eq!(format_decimal(&big(12500), 4), "1.25");
+        assert_eq!(format_decimal(&big(5), 3), "0.005");
+        assert_eq!(format_decimal(&big(300), 2), "3");
+    }
+
+    #[test]
+    fn negative_price_shift() {
+        assert_eq!(parse_decimal("1200", -2).unwrap(), big(12));
+        assert_eq!(format_decimal(&big(12), -2), "1200");
+        assert!(parse_decimal("1250", -2).is_err());
+        assert!(parse_decimal("1200.5", -2).is_err());
+    }
+
+    #[test]
+    fn missing_integer_or_fraction() {
+        assert_eq!(parse_decimal(".5", 1).unwrap(), big(5));
+        assert_eq!(parse_decimal("5.", 1).unwrap(), big(50));
+        assert!(parse_decimal(".", 1).is_err());
+        assert!(parse_decimal("", 1).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(parse_decimal("-1", 2).is_err());
+        assert!(parse_decimal("1.2.3", 2).is_err());
+        assert!(parse_decimal("1e3", 2).is_err());
+    }
+
+    #[test]
+    fn too_many_decimals() {
+        assert!(parse_decimal("0.001", 2).is_err());
+        // trailing zeros don't lose precision
+        assert_eq!(parse_decimal("0.0100", 2).unwrap(), big(1));
+    }
+
+    #[test]
+    fn tick_and_lot_size() {
+        assert!(check_step(&big(1500), &big(500), "price").is_ok());
+        assert!(check_step(&big(1501), &big(500), "price").is_err());
+        assert!(check_step(&big(7), &big(0), "size").is_ok());
+        let lot = parse_decimal("0.01", 6).unwrap();
+        let size = parse_decimal("0.015", 6).unwrap();
+        assert!(check_step(&size, &lot, "size").is_err());
+    }
+}
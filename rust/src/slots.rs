@@ -0,0 +1,184 @@
+use anyhow::{anyhow, ensure, Result};
+use std::collections::BTreeMap;
+
+/// A cancelled slot: the entry it held, its nonce and the lowest nonce a new order in it may use.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Invalidated<T> {
+    pub slot_id: u32,
+    pub cancelled_nonce: u32,
+    pub next_nonce: u32,
+    pub item: T,
+}
+
+/// The live order slots of a sub account with their nonces.
+///
+/// zkLink has no cancel tx. A slot nonce only advances on layer2 when an order in that slot
+/// is filled, and from then on every order of the slot with a lower nonce is rejected.
+/// Cancelling therefore drops the order from the matcher and reserves the next nonce of the
+/// slot, the cancelled order is void for good once an order signed with that nonce is filled.
+pub(crate) struct Slots<T> {
+    live: BTreeMap<u32, (u32, T)>,
+    /// The lowest nonce accepted in a slot after a cancel.
+    min_nonces: BTreeMap<u32, u32>,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Self {
+            live: BTreeMap::new(),
+            min_nonces: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Slots<T> {
+    /// The lowest nonce a new order in `slot_id` may be signed with.
+    pub fn min_nonce(&self, slot_id: u32) -> u32 {
+        let live = self
+            .live
+            .get(&slot_id)
+            .map(|(nonce, _)| *nonce)
+            .unwrap_or_default();
+        let min = self.min_nonces.get(&slot_id).copied().unwrap_or_default();
+        live.max(min)
+    }
+
+    pub fn track(&mut self, slot_id: u32, nonce: u32, item: T) -> Result<()> {
+        let min_nonce = self.min_nonce(slot_id);
+        ensure!(
+            nonce >= min_nonce,
+            "slot {} only accepts nonce {} or above",
+            slot_id,
+            min_nonce
+        );
+        self.live.insert(slot_id, (nonce, item));
+        Ok(())
+    }
+
+    pub fn release(&mut self, slot_id: u32) -> bool {
+        self.live.remove(&slot_id).is_some()
+    }
+
+    pub fn live_slots(&self) -> Vec<u32> {
+        self.live.keys().copied().collect()
+    }
+
+    pub fn next_free_slot(&self) -> u32 {
+        (0..)
+            .find(|slot_id| !self.live.contains_key(slot_id))
+            .unwrap_or_default()
+    }
+
+    fn next_nonce(slot_id: u32, nonce: u32) -> Result<u32> {
+        nonce
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("slot {} has used up its nonces", slot_id))
+    }
+
+    fn invalidate(&mut self, slot_id: u32) -> Result<Invalidated<T>> {
+        let (nonce, item) = self
+            .live
+            .remove(&slot_id)
+            .ok_or_else(|| anyhow!("slot {} is not live", slot_id))?;
+        let next_nonce = Self::next_nonce(slot_id, nonce)?;
+        self.min_nonces.insert(slot_id, next_nonce);
+        Ok(Invalidated {
+            slot_id,
+            cancelled_nonce: nonce,
+            next_nonce,
+            item,
+        })
+    }
+
+    pub fn cancel(&mut self, slot_id: u32) -> Result<Invalidated<T>> {
+        let (nonce, _) = self
+            .live
+            .get(&slot_id)
+            .ok_or_else(|| anyhow!("slot {} is not live", slot_id))?;
+        Self::next_nonce(slot_id, *nonce)?;
+        self.invalidate(slot_id)
+    }
+
+    /// Cancel every live slot, nothing is cancelled if one of them can't be.
+    pub fn cancel_all(&mut self) -> Result<Vec<Invalidated<T>>> {
+        for (slot_id, (nonce, _)) in &self.live {
+            Self::next_nonce(*slot_id, *nonce)?;
+        }
+        self.live_slots()
+            .into_iter()
+            .map(|slot_id| self.invalidate(slot_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_reserves_the_next_nonce() {
+        let mut slots = Slots::<()>::default();
+        slots.track(3, 7, ()).unwrap();
+        assert_eq!(slots.live_slots(), vec![3]);
+        assert_eq!(
+            slots.cancel(3).unwrap(),
+            Invalidated {
+                slot_id: 3,
+                cancelled_nonce: 7,
+                next_nonce: 8,
+                item: ()
+            }
+        );
+        assert!(slots.live_slots().is_empty());
+        assert_eq!(slots.min_nonce(3), 8);
+        // the cancelled order can't be tracked again, the order superseding it can
+        assert!(slots.track(3, 7, ()).is_err());
+        slots.track(3, 8, ()).unwrap();
+    }
+
+    #[test]
+    fn cancel_unknown_slot() {
+        assert!(Slots::<()>::default().cancel(0).is_err());
+    }
+
+    #[test]
+    fn cancel_all() {
+        let mut slots = Slots::<()>::default();
+        slots.track(0, 1, ()).unwrap();
+        slots.track(2, 5, ()).unwrap();
+        assert_eq!(slots.next_free_slot(), 1);
+        let invalidated = slots.cancel_all().unwrap();
+        assert_eq!(
+            invalidated
+                .iter()
+                .map(|slot| (slot.slot_id, slot.next_nonce))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (2, 6)]
+        );
+        assert!(slots.live_slots().is_empty());
+        assert_eq!(slots.next_free_slot(), 0);
+    }
+
+    #[test]
+    fn nonce_overflow_is_an_error() {
+        let mut slots = Slots::<()>::default();
+        slots.track(0, 1, ()).unwrap();
+        slots.track(1, u32::MAX, ()).unwrap();
+        assert!(slots.cancel_all().is_err());
+        // nothing was cancelled
+        assert_eq!(slots.live_slots(), vec![0, 1]);
+        assert!(slots.cancel(1).is_err());
+        assert_eq!(slots.cancel(0).unwrap().next_nonce, 2);
+    }
+
+    #[test]
+    fn older_nonce_is_rejected() {
+        let mut slots = Slots::<()>::default();
+        slots.track(0, 4, ()).unwrap();
+        assert!(slots.track(0, 3, ()).is_err());
+        slots.track(0, 4, ()).unwrap();
+        slots.track(0, 5, ()).unwrap();
+        assert!(slots.release(0));
+        assert!(!slots.release(0));
+    }
+}